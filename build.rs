@@ -3,7 +3,7 @@ fn main() {
     println!("cargo:rerun-if-changed=src/krpc.rs");
     protoc_rust::Codegen::new()
         .out_dir("src/")
-        .inputs(&["protos/krpc.proto"])
+        .inputs(["protos/krpc.proto"])
         .run()
         .expect("protoc");
 }
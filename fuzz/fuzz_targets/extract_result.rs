@@ -0,0 +1,38 @@
+//! Feeds arbitrary bytes to the extractors, which must return errors rather than panic.
+//!
+//! Run with `cargo +nightly fuzz run extract_result` from the repository root.
+#![no_main]
+
+use krpc_mars::codec::{self, Bytes, Value};
+use krpc_mars::krpc;
+use krpc_mars::stream::StreamHandle;
+
+use libfuzzer_sys::fuzz_target;
+
+use std::collections::{HashMap, HashSet};
+
+fuzz_target!(|data: &[u8]| {
+    let mut result = krpc::ProcedureResult::new();
+    result.set_value(data.to_vec());
+
+    let _ = codec::extract_result::<bool>(&result);
+    let _ = codec::extract_result::<f64>(&result);
+    let _ = codec::extract_result::<i32>(&result);
+    let _ = codec::extract_result::<String>(&result);
+    let _ = codec::extract_result::<Option<String>>(&result);
+    let _ = codec::extract_result::<Bytes>(&result);
+    let _ = codec::extract_result::<StreamHandle<f64>>(&result);
+    let _ = codec::extract_result::<krpc::Status>(&result);
+    let _ = codec::extract_result::<krpc::Services>(&result);
+    let _ = codec::extract_result::<(f64, f64, f64)>(&result);
+    let _ = codec::extract_result::<(u32, String, bool, f32, i64, u64)>(&result);
+    let _ = codec::extract_result::<Vec<(u64, Vec<String>)>>(&result);
+    let _ = codec::extract_result::<HashSet<i64>>(&result);
+    let _ = codec::extract_result::<HashMap<String, Vec<f64>>>(&result);
+
+    // Decodes the data with a type parsed from the data itself, for a variety of type trees.
+    if let Ok(ty) = krpc_mars::protobuf::Message::parse_from_bytes(data) {
+        let ty: krpc::Type = ty;
+        let _ = Value::decode(&ty, data);
+    }
+});
@@ -0,0 +1,114 @@
+//! Asynchronous client for sending requests to the KRPC mod, built on tokio.
+//!
+//! This module is only available with the `async` feature.
+use crate::client::{CallHandle, RPCRequest, RPCResponse};
+use crate::codec;
+use crate::error;
+use crate::krpc;
+
+use protobuf::Message;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::net::ToSocketAddrs;
+
+/// An asynchronous client to the RPC server. This is the counterpart of
+/// [`RPCClient`](crate::RPCClient) for programs running on a tokio runtime.
+#[derive(Debug)]
+pub struct AsyncRPCClient {
+    sock: TcpStream,
+    reader: codec::FramedReader,
+    /// Bytes of the last request which are not written yet.
+    outgoing: Vec<u8>,
+    /// Number of requests sent whose response was not read yet. They are left over by calls
+    /// which were cancelled, and their responses are skipped.
+    pending: usize,
+    pub(crate) client_id: Vec<u8>,
+    limits: codec::Limits,
+}
+
+impl AsyncRPCClient {
+    /// Connects to the KRPC server. The client will show up in the KRPC UI with the given client name.
+    pub async fn connect<A: ToSocketAddrs>(
+        client_name: &str,
+        addr: A,
+    ) -> Result<Self, error::ConnectionError> {
+        let mut sock = TcpStream::connect(addr).await?;
+
+        let mut conn_req = krpc::ConnectionRequest::new();
+        conn_req.set_field_type(krpc::ConnectionRequest_Type::RPC);
+        conn_req.set_client_name(client_name.to_string());
+
+        codec::write_message_async(&conn_req, &mut sock).await?;
+
+        let mut reader = codec::FramedReader::default();
+        let response = reader.read_frame_async(&mut sock).await?;
+        let response = krpc::ConnectionResponse::parse_from_bytes(&response)?;
+        let client_id = crate::client::accept_connection(response)?;
+
+        Ok(AsyncRPCClient {
+            sock,
+            reader,
+            outgoing: Vec::new(),
+            pending: 0,
+            client_id,
+            limits: codec::Limits::default(),
+        })
+    }
+
+    /// Sets the limits on the responses accepted from the server.
+    pub fn set_limits(&mut self, limits: codec::Limits) {
+        self.reader.set_max_frame_size(limits.max_frame_size);
+        self.limits = limits;
+    }
+
+    /// Sends a single RPC request to the server.
+    pub async fn mk_call<T: codec::RPCExtractable>(
+        &mut self,
+        call: &CallHandle<T>,
+    ) -> Result<T, error::RPCError> {
+        let (result,) = crate::async_batch_call!(self, (call)).await?;
+        result
+    }
+
+    /// Sends an [`RPCRequest`] to the server. A single RPCRequest may contain multiple RPC calls.
+    /// It is recommended to use the [`async_batch_call!`](crate::async_batch_call) or
+    /// [`async_batch_call_unwrap!`](crate::async_batch_call_unwrap) for one-off requests.
+    ///
+    /// The future is cancel-safe, e.g. when it loses a `tokio::select!` or hits a
+    /// `tokio::time::timeout`. The request may still reach the server, and its response is skipped
+    /// by the next call.
+    pub async fn submit_request(
+        &mut self,
+        request: RPCRequest,
+    ) -> Result<RPCResponse, error::RPCError> {
+        let raw_request = request.build().write_length_delimited_to_bytes()?;
+
+        // Finish what cancelled calls left behind.
+        self.flush().await?;
+        while self.pending > 0 {
+            self.reader.read_frame_async(&mut self.sock).await?;
+            self.pending -= 1;
+        }
+
+        self.outgoing = raw_request;
+        self.pending = 1;
+        self.flush().await?;
+        let frame = self.reader.read_frame_async(&mut self.sock).await?;
+        self.pending = 0;
+        let resp = krpc::Response::parse_from_bytes(&frame)?;
+        RPCResponse::from_message(resp, self.limits)
+    }
+
+    /// Writes the bytes of the last request which are not written yet. Unlike `write_all`, a
+    /// cancelled call doesn't lose track of what was written.
+    async fn flush(&mut self) -> std::io::Result<()> {
+        while !self.outgoing.is_empty() {
+            let n = self.sock.write(&self.outgoing).await?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            self.outgoing.drain(..n);
+        }
+        Ok(())
+    }
+}
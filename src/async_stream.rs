@@ -0,0 +1,112 @@
+//! Asynchronous client to the KRPC Stream server, built on tokio.
+//!
+//! This module is only available with the `async` feature.
+use crate::codec;
+use crate::error;
+use crate::krpc;
+
+use crate::async_client::AsyncRPCClient;
+use crate::stream::StreamUpdate;
+
+use protobuf::Message;
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::net::ToSocketAddrs;
+
+/// An asynchronous client to the Stream server.
+///
+/// The client is a [`futures_core::Stream`] of [`StreamUpdate`]s, so it can be polled alongside
+/// other futures (e.g. in `tokio::select!`). The stream ends when the server closes the
+/// connection.
+#[derive(Debug)]
+pub struct AsyncStreamClient {
+    sock: TcpStream,
+    reader: codec::FramedReader,
+    limits: codec::Limits,
+}
+
+impl AsyncStreamClient {
+    /// Connect to the stream server associated with the given client.
+    pub async fn connect<A: ToSocketAddrs>(
+        client: &AsyncRPCClient,
+        addr: A,
+    ) -> Result<Self, error::ConnectionError> {
+        let mut sock = TcpStream::connect(addr).await?;
+
+        let mut conn_req = krpc::ConnectionRequest::new();
+        conn_req.set_field_type(krpc::ConnectionRequest_Type::STREAM);
+        conn_req.set_client_identifier(client.client_id.clone());
+
+        codec::write_message_async(&conn_req, &mut sock).await?;
+
+        let mut reader = codec::FramedReader::default();
+        let response = reader.read_frame_async(&mut sock).await?;
+        crate::client::accept_connection(krpc::ConnectionResponse::parse_from_bytes(&response)?)?;
+
+        Ok(Self {
+            sock,
+            reader,
+            limits: codec::Limits::default(),
+        })
+    }
+
+    /// Sets the limits on the updates accepted from the server.
+    pub fn set_limits(&mut self, limits: codec::Limits) {
+        self.reader.set_max_frame_size(limits.max_frame_size);
+        self.limits = limits;
+    }
+
+    /// Waits for the next update from the server.
+    pub async fn recv_update(&mut self) -> Result<StreamUpdate, error::RPCError> {
+        match std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next_update(cx)).await {
+            Some(update) => update,
+            None => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+        }
+    }
+
+    fn poll_next_update(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<StreamUpdate, error::RPCError>>> {
+        loop {
+            match self.reader.next_message::<krpc::StreamUpdate>() {
+                Ok(Some(update)) => {
+                    let update = StreamUpdate::from_message(update, self.limits);
+                    return Poll::Ready(Some(Ok(update)));
+                }
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            }
+
+            let mut chunk = [0; 4096];
+            let mut read_buf = ReadBuf::new(&mut chunk);
+            match Pin::new(&mut self.sock).poll_read(cx, &mut read_buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Ok(())) if read_buf.filled().is_empty() => {
+                    if self.reader.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    // The connection was closed in the middle of a message.
+                    self.reader = codec::FramedReader::default();
+                    self.reader.set_max_frame_size(self.limits.max_frame_size);
+                    let e = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                Poll::Ready(Ok(())) => self.reader.extend(read_buf.filled()),
+            }
+        }
+    }
+}
+
+impl futures_core::Stream for AsyncStreamClient {
+    type Item = Result<StreamUpdate, error::RPCError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_update(cx)
+    }
+}
@@ -0,0 +1,168 @@
+//! Configuration of TCP connections to the KRPC server.
+use crate::client::RPCClient;
+use crate::codec;
+use crate::error;
+use crate::stream::StreamClient;
+use crate::transport::Framed;
+
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Builds [`RPCClient`]s and [`StreamClient`]s with custom socket options. Use
+/// [`RPCClient::builder`] to get one.
+///
+/// # Example:
+/// ```rust,no_run
+/// # fn main() -> Result<(), krpc_mars::error::ConnectionError> {
+/// use std::time::Duration;
+///
+/// let builder = krpc_mars::RPCClient::builder()
+///     .name("Example")
+///     .rpc_addr("192.168.1.10:50000")
+///     .stream_addr("192.168.1.10:50001")
+///     .connect_timeout(Duration::from_secs(5))
+///     .read_timeout(Duration::from_secs(2));
+///
+/// let client = builder.connect()?;
+/// let stream_client = builder.connect_stream(&client)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    name: String,
+    rpc_addr: String,
+    stream_addr: String,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    nodelay: bool,
+    limits: codec::Limits,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder {
+            name: String::from("krpc-mars"),
+            rpc_addr: String::from("127.0.0.1:50000"),
+            stream_addr: String::from("127.0.0.1:50001"),
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            nodelay: false,
+            limits: codec::Limits::default(),
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// The name under which the client will show up in the KRPC UI.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Address of the RPC server. Defaults to `127.0.0.1:50000`.
+    pub fn rpc_addr(mut self, addr: &str) -> Self {
+        self.rpc_addr = addr.to_string();
+        self
+    }
+
+    /// Address of the Stream server. Defaults to `127.0.0.1:50001`.
+    pub fn stream_addr(mut self, addr: &str) -> Self {
+        self.stream_addr = addr.to_string();
+        self
+    }
+
+    /// How long to wait for the server to accept the connection and answer the handshake. Waits
+    /// forever by default.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// How long to wait for a response or a stream update before failing with
+    /// [`RPCError::Timeout`](error::RPCError::Timeout). Waits forever by default. A response
+    /// arriving after its request timed out is discarded by the client.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// How long to wait for a request to be sent before failing with
+    /// [`RPCError::Timeout`](error::RPCError::Timeout). Waits forever by default.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on the sockets, so that small requests are sent right away.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Bounds on the messages and values accepted from the server. See [`codec::Limits`] for the
+    /// defaults.
+    pub fn limits(mut self, limits: codec::Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Connects to the RPC server.
+    pub fn connect(&self) -> Result<RPCClient, error::ConnectionError> {
+        let sock = self.open(&self.rpc_addr)?;
+        let mut client = RPCClient::with_transport(&self.name, Framed::new(sock))?;
+        client.set_limits(self.limits);
+        self.finish(client.transport_mut().get_mut())?;
+        Ok(client)
+    }
+
+    /// Connects to the Stream server associated with the given client.
+    pub fn connect_stream<C>(
+        &self,
+        client: &RPCClient<C>,
+    ) -> Result<StreamClient, error::ConnectionError> {
+        let sock = self.open(&self.stream_addr)?;
+        let mut stream_client = StreamClient::with_transport(client, Framed::new(sock))?;
+        stream_client.set_limits(self.limits);
+        self.finish(stream_client.transport_mut().get_mut())?;
+        Ok(stream_client)
+    }
+
+    /// Opens a socket ready for the handshake, which is bounded by the connect timeout.
+    fn open(&self, addr: &str) -> Result<TcpStream, error::ConnectionError> {
+        let sock = match self.connect_timeout {
+            Some(timeout) => connect_timeout(addr, timeout)?,
+            None => TcpStream::connect(addr)?,
+        };
+        sock.set_nodelay(self.nodelay)?;
+        sock.set_read_timeout(self.connect_timeout)?;
+        sock.set_write_timeout(self.connect_timeout)?;
+        Ok(sock)
+    }
+
+    /// Applies the timeouts for regular operation once the handshake is done.
+    fn finish(&self, sock: &mut TcpStream) -> Result<(), error::ConnectionError> {
+        sock.set_read_timeout(self.read_timeout)?;
+        sock.set_write_timeout(self.write_timeout)?;
+        Ok(())
+    }
+}
+
+/// Tries each address `addr` resolves to, like `TcpStream::connect` does.
+fn connect_timeout(addr: &str, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(sock) => return Ok(sock),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "could not resolve to any address",
+        )
+    }))
+}
@@ -0,0 +1,92 @@
+//! A cache of the latest stream values, filled by a background thread.
+use crate::codec;
+use crate::error;
+use crate::stream::{StreamClient, StreamHandle, StreamUpdate};
+use crate::transport::Transport;
+use crate::worker::Worker;
+
+use std::sync::{Arc, RwLock};
+
+/// Receives stream updates on a background thread and keeps the latest value of every stream.
+///
+/// The server only sends the values which changed, so the cache merges each update into the
+/// previous ones. Values can be read from any thread (wrap the cache in an `Arc` to share it)
+/// without waiting for an update. Dropping the cache stops the thread.
+///
+/// # Example:
+/// ```rust,ignore
+///let ut_stream_handle = client.mk_call(&space_center::get_ut().to_stream())?;
+///let cache = krpc_mars::StreamCache::spawn(stream_client);
+///
+///loop {
+///    if let Some(ut) = cache.get(&ut_stream_handle) {
+///        println!("ut: {}", ut);
+///    }
+///    if !cache.is_running() {
+///        match cache.take_error() {
+///            Some(e) => return Err(e.into()),
+///            None => break,
+///        }
+///    }
+///    std::thread::sleep(std::time::Duration::from_millis(100));
+///}
+/// ```
+#[derive(Debug)]
+pub struct StreamCache {
+    latest: Arc<RwLock<StreamUpdate>>,
+    receiver: Worker,
+}
+
+impl StreamCache {
+    /// Moves the stream client to a new thread which receives updates until the connection fails.
+    pub fn spawn<T>(stream_client: StreamClient<T>) -> Self
+    where
+        T: Transport + Send + 'static,
+    {
+        let latest = Arc::new(RwLock::new(StreamUpdate::default()));
+        let receiver = {
+            let latest = Arc::clone(&latest);
+            Worker::spawn(
+                stream_client,
+                move |update| latest.write().unwrap().merge_with(update),
+                || {},
+            )
+        };
+
+        StreamCache { latest, receiver }
+    }
+
+    /// Returns the latest value of a stream, or `None` if no value was received yet or if it
+    /// could not be decoded.
+    pub fn get<T>(&self, handle: &StreamHandle<T>) -> Option<T>
+    where
+        T: codec::RPCExtractable,
+    {
+        self.try_get(handle).ok().flatten()
+    }
+
+    /// Returns the latest value of a stream, or `None` if no value was received yet.
+    pub fn try_get<T>(&self, handle: &StreamHandle<T>) -> Result<Option<T>, error::RPCError>
+    where
+        T: codec::RPCExtractable,
+    {
+        self.latest.read().unwrap().get_result(handle)
+    }
+
+    /// Returns a copy of the latest values of all streams.
+    pub fn snapshot(&self) -> StreamUpdate {
+        self.latest.read().unwrap().clone()
+    }
+
+    /// Returns `false` once the receiver thread has stopped. Values are not updated anymore after
+    /// that, and [`StreamCache::take_error`] tells why the thread stopped.
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_running()
+    }
+
+    /// Returns the error which stopped the receiver thread, if it stopped. The error is only
+    /// returned once.
+    pub fn take_error(&self) -> Option<error::RPCError> {
+        self.receiver.take_error()
+    }
+}
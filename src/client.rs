@@ -1,22 +1,66 @@
 //! Client for sending requests to the KRPC mod.
 use crate::codec;
+use crate::dynamic;
 use crate::error;
 use crate::krpc;
 
 use crate::stream::StreamHandle;
+use crate::transport::{Framed, Transport};
 
 use std::net::TcpStream;
 use std::net::ToSocketAddrs;
 
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
 
 use protobuf::Message;
 
-/// A client to the RPC server.
+/// A client to the RPC server. The type parameter is the [`Transport`] used to reach the server,
+/// plain TCP by default.
 #[derive(Debug)]
-pub struct RPCClient {
-    sock: TcpStream,
+pub struct RPCClient<T = Framed<TcpStream>> {
+    transport: T,
     pub(crate) client_id: Vec<u8>,
+    /// Ticket given to the next request sent.
+    next_ticket: u64,
+    /// Ticket of the next response to be read from the transport.
+    next_response: u64,
+    /// Responses read ahead while waiting for a later ticket.
+    received: HashMap<u64, Result<RPCResponse, error::RPCError>>,
+    /// Tickets whose response nobody will retrieve, because they were dropped or waiting for the
+    /// response failed. Their responses are discarded. Shared with the tickets in flight.
+    abandoned: Arc<Mutex<HashSet<u64>>>,
+    /// Procedures provided by the server, fetched by the first dynamic call.
+    pub(crate) schema: Option<crate::dynamic::Schema>,
+    pub(crate) limits: codec::Limits,
+}
+
+/// Identifies a request sent with [`RPCClient::send_request`]. Pass it to
+/// [`RPCClient::recv_response`] to get the response. If the ticket is dropped instead, the client
+/// discards the response.
+#[derive(Debug)]
+#[must_use = "the response can only be retrieved with the ticket"]
+pub struct Ticket {
+    id: u64,
+    /// The abandoned tickets of the client which issued this one. Taken when the ticket is used.
+    abandoned: Option<Arc<Mutex<HashSet<u64>>>>,
+}
+
+impl PartialEq for Ticket {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Ticket {}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if let Some(abandoned) = self.abandoned.take() {
+            abandoned.lock().unwrap().insert(self.id);
+        }
+    }
 }
 
 /// Represents a request that can be submitted to the RPCServer. This object is clonable so that
@@ -25,7 +69,7 @@ pub struct RPCClient {
 /// [`RPCClient::mk_call`] which provide a nicer API.
 #[derive(Clone, Default)]
 pub struct RPCRequest {
-    calls: protobuf::RepeatedField<krpc::ProcedureCall>,
+    pub(crate) calls: protobuf::RepeatedField<krpc::ProcedureCall>,
 }
 
 impl RPCRequest {
@@ -33,7 +77,7 @@ impl RPCRequest {
         self.calls.push(handle.get_call().clone())
     }
 
-    fn build(self) -> krpc::Request {
+    pub(crate) fn build(self) -> krpc::Request {
         let mut req = krpc::Request::new();
         req.set_calls(self.calls);
         req
@@ -41,9 +85,28 @@ impl RPCRequest {
 }
 
 /// A response from the RPC Server
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct RPCResponse {
-    results: protobuf::RepeatedField<krpc::ProcedureResult>,
+    pub(crate) results: protobuf::RepeatedField<krpc::ProcedureResult>,
+    /// Limits of the client which received the response, checked when extracting results.
+    pub(crate) limits: codec::Limits,
+}
+
+impl RPCResponse {
+    /// Turns a response read from the server into its results, or the error it reports.
+    pub(crate) fn from_message(
+        mut resp: krpc::Response,
+        limits: codec::Limits,
+    ) -> Result<Self, error::RPCError> {
+        if resp.has_error() {
+            Err(error::RPCError::KRPCRequestErr(resp.take_error()))
+        } else {
+            Ok(RPCResponse {
+                results: resp.take_results(),
+                limits,
+            })
+        }
+    }
 }
 
 /// Represents a procedure call. The type parameter is the type of the value to be extracted from
@@ -73,9 +136,69 @@ where
         crate::stream::mk_stream(self)
     }
 
+    /// Creates a streamed version of this call which doesn't send updates until
+    /// [`StreamHandle::start`] is called. This lets you set the rate of the stream first.
+    pub fn to_stream_paused(&self) -> CallHandle<StreamHandle<T>> {
+        crate::stream::mk_paused_stream(self)
+    }
+
+    /// Replaces the argument at the given position, or adds it if the call doesn't have it.
+    pub fn with_arg<A: codec::RPCEncodable + ?Sized>(mut self, position: u32, value: &A) -> Self {
+        let value = value.encode_to_bytes().unwrap();
+        let arguments = self.proc_call.mut_arguments();
+        match arguments.iter_mut().find(|a| a.position == position) {
+            Some(arg) => arg.set_value(value),
+            None => {
+                let mut arg = krpc::Argument::new();
+                arg.set_position(position);
+                arg.set_value(value);
+                arguments.push(arg);
+            }
+        }
+        self
+    }
+
+    /// Removes the argument at the given position. The server uses the default value of the
+    /// parameter instead, if it has one.
+    pub fn without_arg(mut self, position: u32) -> Self {
+        self.proc_call
+            .mut_arguments()
+            .retain(|a| a.position != position);
+        self
+    }
+
+    /// Adds the default value of every parameter of `procedure` for which the call has no
+    /// argument. The procedure can be obtained with [`RPCClient::procedure`].
+    pub fn with_defaults(mut self, procedure: &krpc::Procedure) -> Self {
+        for (position, parameter) in procedure.parameters.iter().enumerate() {
+            let position = position as u32;
+            let present = self
+                .proc_call
+                .arguments
+                .iter()
+                .any(|a| a.position == position);
+            if present {
+                continue;
+            }
+            if let Some(default) = dynamic::default_value(parameter) {
+                let mut arg = krpc::Argument::new();
+                arg.set_position(position);
+                arg.set_value(default);
+                self.proc_call.mut_arguments().push(arg);
+            }
+        }
+        self
+    }
+
     /// Extract the i-th result from a response.
     pub fn get_result(&self, resp: &RPCResponse, idx: usize) -> Result<T, error::RPCError> {
-        codec::extract_result(&resp.results[idx])
+        let result = resp.results.get(idx).ok_or_else(|| {
+            codec::DecodeError::new(
+                format!("a result at index {}", idx),
+                format!("{} results", resp.results.len()),
+            )
+        })?;
+        codec::with_limits(&resp.limits, || codec::extract_result(result))
     }
 
     pub(crate) fn get_call(&self) -> &krpc::ProcedureCall {
@@ -83,39 +206,73 @@ where
     }
 }
 
+/// Checks the server's answer to a connection request and returns the client identifier it
+/// assigned to us.
+pub(crate) fn accept_connection(
+    mut response: krpc::ConnectionResponse,
+) -> Result<Vec<u8>, error::ConnectionError> {
+    match response.status {
+        krpc::ConnectionResponse_Status::OK => Ok(response.take_client_identifier()),
+        s => Err(error::ConnectionError::ConnectionRefused {
+            error: response.take_message(),
+            status: s,
+        }),
+    }
+}
+
 impl RPCClient {
     /// Connects to the KRPC server. The client will show up in the KRPC UI with the given client name.
     pub fn connect<A: ToSocketAddrs>(
         client_name: &str,
         addr: A,
     ) -> Result<Self, error::ConnectionError> {
-        let mut sock = TcpStream::connect(addr)?;
+        let sock = TcpStream::connect(addr)?;
+        Self::with_transport(client_name, Framed::new(sock))
+    }
+
+    /// Returns a builder to configure the connection (addresses, timeouts, ...).
+    pub fn builder() -> crate::builder::ClientBuilder {
+        crate::builder::ClientBuilder::default()
+    }
+}
 
+impl<T: Transport> RPCClient<T> {
+    /// Connects to the KRPC server through an already established transport. The client will
+    /// show up in the KRPC UI with the given client name.
+    pub fn with_transport(
+        client_name: &str,
+        mut transport: T,
+    ) -> Result<Self, error::ConnectionError> {
         let mut conn_req = krpc::ConnectionRequest::new();
         conn_req.set_field_type(krpc::ConnectionRequest_Type::RPC);
         conn_req.set_client_name(client_name.to_string());
 
-        conn_req.write_length_delimited_to_writer(&mut sock)?;
+        let response = transport.handshake(&conn_req)?;
+        let client_id = accept_connection(response)?;
 
-        let mut response = codec::read_message::<krpc::ConnectionResponse>(&mut sock)?;
+        Ok(RPCClient {
+            transport,
+            client_id,
+            next_ticket: 0,
+            next_response: 0,
+            received: HashMap::new(),
+            abandoned: Arc::default(),
+            schema: None,
+            limits: codec::Limits::default(),
+        })
+    }
 
-        match response.status {
-            krpc::ConnectionResponse_Status::OK => Ok(RPCClient {
-                sock,
-                client_id: response.client_identifier,
-            }),
-            s => Err(error::ConnectionError::ConnectionRefused {
-                error: response.take_message(),
-                status: s,
-            }),
-        }
+    /// Sets the limits on the responses accepted from the server.
+    pub fn set_limits(&mut self, limits: codec::Limits) {
+        self.transport.set_limits(&limits);
+        self.limits = limits;
     }
 
     /// Sends a single RPC request to the server.
-    pub fn mk_call<T: codec::RPCExtractable>(
+    pub fn mk_call<R: codec::RPCExtractable>(
         &mut self,
-        call: &CallHandle<T>,
-    ) -> Result<T, error::RPCError> {
+        call: &CallHandle<R>,
+    ) -> Result<R, error::RPCError> {
         let (result,) = crate::batch_call!(self, (call))?;
         result
     }
@@ -124,14 +281,91 @@ impl RPCClient {
     /// It is recommended to use the [`batch_call!`](crate::batch_call) or
     /// [`batch_call_unwrap!`](crate::batch_call_unwrap) for one-off requests.
     pub fn submit_request(&mut self, request: RPCRequest) -> Result<RPCResponse, error::RPCError> {
+        let ticket = self.send_request(request)?;
+        self.recv_response(ticket)
+    }
+
+    /// Sends an [`RPCRequest`] without waiting for the response. Several requests can be in
+    /// flight at the same time; the server answers them in order.
+    ///
+    /// # Example:
+    /// ```rust,ignore
+    ///let mut request = krpc_mars::RPCRequest::default();
+    ///request.add_call(&space_center::get_ut());
+    ///let ticket = client.send_request(request)?;
+    ///
+    ///// ... send more requests or do something else ...
+    ///
+    ///let response = client.recv_response(ticket)?;
+    ///let ut = space_center::get_ut().get_result(&response, 0)?;
+    /// ```
+    pub fn send_request(&mut self, request: RPCRequest) -> Result<Ticket, error::RPCError> {
         let raw_request = request.build();
-        raw_request.write_length_delimited_to_writer(&mut self.sock)?;
-        let mut resp = codec::read_message::<krpc::Response>(&mut self.sock)?;
-        if resp.has_error() {
-            Err(error::RPCError::KRPCRequestErr(resp.take_error()))
-        } else {
-            let results = resp.take_results();
-            Ok(RPCResponse { results })
+        self.transport.send_message(&raw_request)?;
+        let ticket = Ticket {
+            id: self.next_ticket,
+            abandoned: Some(Arc::clone(&self.abandoned)),
+        };
+        self.next_ticket += 1;
+        Ok(ticket)
+    }
+
+    /// Waits for the response to a request sent with [`RPCClient::send_request`]. Responses to
+    /// earlier requests which haven't been retrieved yet are kept until their ticket is used.
+    ///
+    /// If waiting fails, e.g. with [`RPCError::Timeout`](error::RPCError::Timeout), the response
+    /// can't be retrieved anymore: it is discarded when it arrives, and the client can go on with
+    /// other requests.
+    pub fn recv_response(&mut self, mut ticket: Ticket) -> Result<RPCResponse, error::RPCError> {
+        match ticket.abandoned.take() {
+            Some(abandoned) if Arc::ptr_eq(&abandoned, &self.abandoned) => {}
+            // Dropping the ticket of another client marks it abandoned there.
+            abandoned => {
+                ticket.abandoned = abandoned;
+                return Err(error::RPCError::UnknownTicket);
+            }
+        }
+        self.discard_abandoned();
+
+        while self.next_response <= ticket.id {
+            let frame = match self.transport.recv_frame() {
+                Ok(frame) => frame,
+                Err(e) => {
+                    self.abandoned.lock().unwrap().insert(ticket.id);
+                    return Err(e.into());
+                }
+            };
+            // Once read, the frame is the response to the next request, even if it is malformed.
+            let id = self.next_response;
+            self.next_response += 1;
+            if !self.abandoned.lock().unwrap().remove(&id) {
+                let response = krpc::Response::parse_from_bytes(&frame)
+                    .map_err(error::RPCError::from)
+                    .and_then(|resp| RPCResponse::from_message(resp, self.limits));
+                self.received.insert(id, response);
+            }
         }
+
+        self.received
+            .remove(&ticket.id)
+            .unwrap_or(Err(error::RPCError::UnknownTicket))
+    }
+
+    /// Drops the responses read ahead for tickets which were dropped since.
+    fn discard_abandoned(&mut self) {
+        let received = &mut self.received;
+        self.abandoned
+            .lock()
+            .unwrap()
+            .retain(|id| received.remove(id).is_none());
+    }
+
+    /// Returns the transport used by this client.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub(crate) fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
     }
 }
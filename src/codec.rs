@@ -6,6 +6,7 @@ use crate::error;
 use protobuf;
 use protobuf::Message;
 
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::hash::Hash;
@@ -89,6 +90,48 @@ impl RPCExtractable for String {
     }
 }
 
+/// A byte string, for procedures taking or returning `BYTES`. `Vec<u8>` can't be used for this
+/// since it is encoded as a list of integers.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bytes(pub Vec<u8>);
+
+impl std::ops::Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0
+    }
+}
+
+impl RPCExtractable for Bytes {
+    fn extract_value(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Self, protobuf::ProtobufError> {
+        Ok(Bytes(input.read_bytes()?))
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl RPCExtractable for bytes::Bytes {
+    fn extract_value(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Self, protobuf::ProtobufError> {
+        Ok(input.read_bytes()?.into())
+    }
+}
+
 impl<T> RPCExtractable for crate::stream::StreamHandle<T>
 where
     T: RPCExtractable,
@@ -96,12 +139,46 @@ where
     fn extract_value(
         input: &mut protobuf::CodedInputStream,
     ) -> Result<Self, protobuf::ProtobufError> {
-        let mut stream = krpc::Stream::new();
-        stream.merge_from(input)?;
+        let stream = krpc::Stream::extract_value(input)?;
         Ok(crate::stream::StreamHandle::new(stream.id))
     }
 }
 
+/// Extracts and encodes messages of the KRPC protocol which procedures take or return, such as
+/// `krpc::Status`.
+macro_rules! message_codec {
+    ($($message:ty),*) => {
+        $(
+            impl RPCExtractable for $message {
+                fn extract_value(
+                    input: &mut protobuf::CodedInputStream,
+                ) -> Result<Self, protobuf::ProtobufError> {
+                    let mut message = <$message>::new();
+                    message.merge_from(input)?;
+                    Ok(message)
+                }
+            }
+
+            impl RPCEncodable for $message {
+                fn encode(
+                    &self,
+                    output: &mut protobuf::CodedOutputStream,
+                ) -> Result<(), protobuf::ProtobufError> {
+                    self.write_to(output)
+                }
+            }
+        )*
+    };
+}
+
+message_codec!(
+    krpc::Status,
+    krpc::Services,
+    krpc::ProcedureCall,
+    krpc::Event,
+    krpc::Stream
+);
+
 impl<T> RPCExtractable for Vec<T>
 where
     T: RPCExtractable,
@@ -109,13 +186,11 @@ where
     fn extract_value(
         input: &mut protobuf::CodedInputStream,
     ) -> Result<Self, protobuf::ProtobufError> {
-        let mut m = krpc::List::new();
-        m.merge_from(input)?;
+        let items = read_items(input)?;
 
-        let mut v = Vec::with_capacity(m.items.len());
-        for item in &m.items {
-            let mut i = protobuf::CodedInputStream::from_bytes(&item);
-            v.push(RPCExtractable::extract_value(&mut i)?);
+        let mut v = Vec::with_capacity(items.len());
+        for (i, item) in items.iter().enumerate() {
+            v.push(extract_nested(item, format_args!("[{}]", i))?);
         }
 
         Ok(v)
@@ -129,13 +204,11 @@ where
     fn extract_value(
         input: &mut protobuf::CodedInputStream,
     ) -> Result<Self, protobuf::ProtobufError> {
-        let mut m = krpc::Set::new();
-        m.merge_from(input)?;
+        let items = read_items(input)?;
 
-        let mut s = HashSet::with_capacity(m.items.len());
-        for item in &m.items {
-            let mut i = protobuf::CodedInputStream::from_bytes(&item);
-            s.insert(RPCExtractable::extract_value(&mut i)?);
+        let mut s = HashSet::with_capacity(items.len());
+        for (i, item) in items.iter().enumerate() {
+            s.insert(extract_nested(item, format_args!("[{}]", i))?);
         }
 
         Ok(s)
@@ -150,15 +223,13 @@ where
     fn extract_value(
         input: &mut protobuf::CodedInputStream,
     ) -> Result<Self, protobuf::ProtobufError> {
-        let mut m = krpc::Dictionary::new();
-        m.merge_from(input)?;
-
-        let mut h = HashMap::with_capacity(m.entries.len());
-        for entry in &m.entries {
-            let mut i_k = protobuf::CodedInputStream::from_bytes(&entry.key);
-            let mut i_v = protobuf::CodedInputStream::from_bytes(&entry.value);
-            let key = RPCExtractable::extract_value(&mut i_k)?;
-            let val = RPCExtractable::extract_value(&mut i_v)?;
+        let entries = read_items(input)?;
+
+        let mut h = HashMap::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            let entry = krpc::DictionaryEntry::parse_from_bytes(entry)?;
+            let key = extract_nested(&entry.key, format_args!("[{}].key", i))?;
+            let val = extract_nested(&entry.value, format_args!("[{}].value", i))?;
             h.insert(key, val);
         }
 
@@ -166,78 +237,6 @@ where
     }
 }
 
-impl<T, U> RPCExtractable for (T, U)
-where
-    T: RPCExtractable,
-    U: RPCExtractable,
-{
-    fn extract_value(
-        input: &mut protobuf::CodedInputStream,
-    ) -> Result<Self, protobuf::ProtobufError> {
-        let mut l = krpc::Tuple::new();
-        l.merge_from(input)?;
-        let t = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[0],
-        ))?;
-        let u = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[1],
-        ))?;
-        Ok((t, u))
-    }
-}
-
-impl<T, U, V> RPCExtractable for (T, U, V)
-where
-    T: RPCExtractable,
-    U: RPCExtractable,
-    V: RPCExtractable,
-{
-    fn extract_value(
-        input: &mut protobuf::CodedInputStream,
-    ) -> Result<Self, protobuf::ProtobufError> {
-        let mut l = krpc::Tuple::new();
-        l.merge_from(input)?;
-        let t = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[0],
-        ))?;
-        let u = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[1],
-        ))?;
-        let v = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[2],
-        ))?;
-        Ok((t, u, v))
-    }
-}
-
-impl<T, U, V, W> RPCExtractable for (T, U, V, W)
-where
-    T: RPCExtractable,
-    U: RPCExtractable,
-    V: RPCExtractable,
-    W: RPCExtractable,
-{
-    fn extract_value(
-        input: &mut protobuf::CodedInputStream,
-    ) -> Result<Self, protobuf::ProtobufError> {
-        let mut l = krpc::Tuple::new();
-        l.merge_from(input)?;
-        let t = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[0],
-        ))?;
-        let u = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[1],
-        ))?;
-        let v = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[2],
-        ))?;
-        let w = RPCExtractable::extract_value(&mut protobuf::CodedInputStream::from_bytes(
-            &l.items[3],
-        ))?;
-        Ok((t, u, v, w))
-    }
-}
-
 pub trait RPCEncodable {
     fn encode(
         &self,
@@ -326,6 +325,116 @@ impl RPCEncodable for String {
     }
 }
 
+impl RPCEncodable for Bytes {
+    fn encode(
+        &self,
+        output: &mut protobuf::CodedOutputStream,
+    ) -> Result<(), protobuf::ProtobufError> {
+        output.write_bytes_no_tag(&self.0)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl RPCEncodable for bytes::Bytes {
+    fn encode(
+        &self,
+        output: &mut protobuf::CodedOutputStream,
+    ) -> Result<(), protobuf::ProtobufError> {
+        output.write_bytes_no_tag(self)
+    }
+}
+
+/// Types whose values can be null, so that they can be used as `Option<T>`.
+///
+/// By default, null is the object id 0, as kRPC encodes null class references. Class types only
+/// need an empty impl:
+///
+/// ```rust,ignore
+///impl krpc_mars::codec::Nullable for Vessel {}
+/// ```
+///
+/// Strings and byte arrays are null when their payload is empty.
+pub trait Nullable: Sized {
+    /// Extracts a value, or `None` if it is null.
+    fn extract_nullable(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Option<Self>, protobuf::ProtobufError>
+    where
+        Self: RPCExtractable,
+    {
+        match input.read_uint64()? {
+            0 => Ok(None),
+            id => {
+                let bytes = id.encode_to_bytes()?;
+                let mut input = protobuf::CodedInputStream::from_bytes(&bytes);
+                Self::extract_value(&mut input).map(Some)
+            }
+        }
+    }
+
+    /// Encodes null.
+    fn encode_null(
+        output: &mut protobuf::CodedOutputStream,
+    ) -> Result<(), protobuf::ProtobufError> {
+        output.write_uint64_no_tag(0)
+    }
+}
+
+macro_rules! empty_nullable {
+    ($($ty:ty),*) => {
+        $(
+            impl Nullable for $ty {
+                fn extract_nullable(
+                    input: &mut protobuf::CodedInputStream,
+                ) -> Result<Option<Self>, protobuf::ProtobufError> {
+                    if input.eof()? {
+                        Ok(None)
+                    } else {
+                        Self::extract_value(input).map(Some)
+                    }
+                }
+
+                fn encode_null(
+                    _output: &mut protobuf::CodedOutputStream,
+                ) -> Result<(), protobuf::ProtobufError> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+empty_nullable!(String, Bytes);
+
+#[cfg(feature = "bytes")]
+empty_nullable!(bytes::Bytes);
+
+impl<T> RPCExtractable for Option<T>
+where
+    T: Nullable + RPCExtractable,
+{
+    fn extract_value(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Self, protobuf::ProtobufError> {
+        T::extract_nullable(input)
+    }
+}
+
+impl<T> RPCEncodable for Option<T>
+where
+    T: Nullable + RPCEncodable,
+{
+    fn encode(
+        &self,
+        output: &mut protobuf::CodedOutputStream,
+    ) -> Result<(), protobuf::ProtobufError> {
+        match self {
+            Some(value) => value.encode(output),
+            None => T::encode_null(output),
+        }
+    }
+}
+
 impl<T> RPCEncodable for Vec<T>
 where
     T: RPCEncodable,
@@ -347,77 +456,690 @@ where
     }
 }
 
-impl<T, U> RPCEncodable for (T, U)
+impl<T> RPCEncodable for HashSet<T>
 where
     T: RPCEncodable,
-    U: RPCEncodable,
 {
     fn encode(
         &self,
         output: &mut protobuf::CodedOutputStream,
     ) -> Result<(), protobuf::ProtobufError> {
-        let &(ref t, ref u) = self;
-        let mut tuple = krpc::Tuple::new();
-        tuple.mut_items().push(t.encode_to_bytes()?);
-        tuple.mut_items().push(u.encode_to_bytes()?);
-        tuple.write_to(output)?;
+        let mut set = krpc::Set::new();
+        for e in self {
+            set.mut_items().push(e.encode_to_bytes()?);
+        }
+        set.write_to(output)?;
+
         Ok(())
     }
 }
 
-impl<T, U, V> RPCEncodable for (T, U, V)
+impl<T, U> RPCEncodable for HashMap<T, U>
 where
     T: RPCEncodable,
     U: RPCEncodable,
-    V: RPCEncodable,
 {
     fn encode(
         &self,
         output: &mut protobuf::CodedOutputStream,
     ) -> Result<(), protobuf::ProtobufError> {
-        let &(ref t, ref u, ref v) = self;
-        let mut tuple = krpc::Tuple::new();
-        tuple.mut_items().push(t.encode_to_bytes()?);
-        tuple.mut_items().push(u.encode_to_bytes()?);
-        tuple.mut_items().push(v.encode_to_bytes()?);
-        tuple.write_to(output)?;
+        let mut dictionary = krpc::Dictionary::new();
+        for (key, value) in self {
+            let mut entry = krpc::DictionaryEntry::new();
+            entry.set_key(key.encode_to_bytes()?);
+            entry.set_value(value.encode_to_bytes()?);
+            dictionary.mut_entries().push(entry);
+        }
+        dictionary.write_to(output)?;
+
         Ok(())
     }
 }
 
-impl<T, U, V, W> RPCEncodable for (T, U, V, W)
-where
-    T: RPCEncodable,
-    U: RPCEncodable,
-    V: RPCEncodable,
-    W: RPCEncodable,
-{
-    fn encode(
-        &self,
-        output: &mut protobuf::CodedOutputStream,
-    ) -> Result<(), protobuf::ProtobufError> {
-        let &(ref t, ref u, ref v, ref w) = self;
-        let mut tuple = krpc::Tuple::new();
-        tuple.mut_items().push(t.encode_to_bytes()?);
-        tuple.mut_items().push(u.encode_to_bytes()?);
-        tuple.mut_items().push(v.encode_to_bytes()?);
-        tuple.mut_items().push(w.encode_to_bytes()?);
-        tuple.write_to(output)?;
+/// Extracts the i-th item of a tuple.
+fn extract_item<T: RPCExtractable>(
+    tuple: &krpc::Tuple,
+    index: usize,
+) -> Result<T, protobuf::ProtobufError> {
+    extract_nested(&tuple.items[index], format_args!(".{}", index))
+}
+
+/// Checks that a tuple has as many items as the Rust tuple it is extracted to.
+fn check_tuple_len(tuple: &krpc::Tuple, len: usize) -> Result<(), protobuf::ProtobufError> {
+    if tuple.items.len() == len {
         Ok(())
+    } else {
+        Err(DecodeError::new(
+            format!("a tuple of {} items", len),
+            format!("{} items", tuple.items.len()),
+        )
+        .into())
     }
 }
 
-/// Reads a protobuf message from a source.
-pub(crate) fn read_message<M>(sock: &mut dyn Read) -> Result<M, protobuf::ProtobufError>
+/// Implements both traits for tuples, which kRPC encodes as a `krpc::Tuple` message.
+macro_rules! tuple_codec {
+    ($( ( $( $name:ident $index:tt ),+ ) )+) => {
+        $(
+            impl<$( $name ),+> RPCExtractable for ( $( $name, )+ )
+            where
+                $( $name: RPCExtractable, )+
+            {
+                fn extract_value(
+                    input: &mut protobuf::CodedInputStream,
+                ) -> Result<Self, protobuf::ProtobufError> {
+                    let mut tuple = krpc::Tuple::new();
+                    tuple.merge_from(input)?;
+                    check_tuple_len(&tuple, [$( $index ),+].len())?;
+                    Ok(( $( extract_item::<$name>(&tuple, $index)?, )+ ))
+                }
+            }
+
+            impl<$( $name ),+> RPCEncodable for ( $( $name, )+ )
+            where
+                $( $name: RPCEncodable, )+
+            {
+                fn encode(
+                    &self,
+                    output: &mut protobuf::CodedOutputStream,
+                ) -> Result<(), protobuf::ProtobufError> {
+                    let mut tuple = krpc::Tuple::new();
+                    $( tuple.mut_items().push(self.$index.encode_to_bytes()?); )+
+                    tuple.write_to(output)?;
+                    Ok(())
+                }
+            }
+        )+
+    };
+}
+
+tuple_codec! {
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11)
+}
+
+/// A value of any type supported by kRPC, for procedures without generated bindings. The
+/// [`krpc::Type`] of a value is needed to decode or encode it.
+///
+/// Values use the same encoding as the typed [`RPCExtractable`] and [`RPCEncodable`]
+/// implementations, so one can be converted to the other:
+///
+/// ```rust,ignore
+///let value = Value::decode(&ty, result.get_value())?;
+///let positions: Vec<(f64, f64, f64)> = value.to_typed(&ty)?;
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// The absence of a value, for procedures which don't return anything or return null. Also
+    /// stands for null when encoding a class, string or bytes value.
+    None,
+    Double(f64),
+    Float(f32),
+    SInt32(i32),
+    SInt64(i64),
+    UInt32(u32),
+    UInt64(u64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    /// The id of an object. 0 stands for a null object.
+    Class(u64),
+    /// The value of an enumeration member.
+    Enumeration(i32),
+    Event(krpc::Event),
+    ProcedureCall(krpc::ProcedureCall),
+    Stream(krpc::Stream),
+    Status(krpc::Status),
+    Services(krpc::Services),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Set(Vec<Value>),
+    Dictionary(Vec<(Value, Value)>),
+}
+
+impl Value {
+    /// Decodes a value of the given type. Values which can't be decoded are reported as
+    /// [`RPCError::Decode`](error::RPCError::Decode).
+    pub fn decode(ty: &krpc::Type, bytes: &[u8]) -> Result<Value, error::RPCError> {
+        Value::decode_value(ty, bytes).map_err(|e| match e {
+            error::RPCError::ProtobufErr(e) => {
+                DecodeError::new(type_name(ty), e.to_string()).into()
+            }
+            e => e,
+        })
+    }
+
+    /// Decodes a value nested in another one. Errors record the position of the value, given by
+    /// `segment`.
+    fn decode_nested(
+        ty: &krpc::Type,
+        bytes: &[u8],
+        segment: std::fmt::Arguments,
+    ) -> Result<Value, error::RPCError> {
+        nested(|| Value::decode(ty, bytes)).map_err(|e| match e {
+            error::RPCError::Decode {
+                expected,
+                found,
+                path,
+            } => error::RPCError::Decode {
+                expected,
+                found,
+                path: format!("{}{}", segment, path),
+            },
+            e => e,
+        })
+    }
+
+    fn decode_value(ty: &krpc::Type, bytes: &[u8]) -> Result<Value, error::RPCError> {
+        use krpc::Type_TypeCode as Code;
+
+        let mut input = protobuf::CodedInputStream::from_bytes(bytes);
+        let value = match ty.code {
+            Code::NONE => Value::None,
+            Code::DOUBLE => Value::Double(input.read_double()?),
+            Code::FLOAT => Value::Float(input.read_float()?),
+            Code::SINT32 => Value::SInt32(input.read_sint32()?),
+            Code::SINT64 => Value::SInt64(input.read_sint64()?),
+            Code::UINT32 => Value::UInt32(input.read_uint32()?),
+            Code::UINT64 => Value::UInt64(input.read_uint64()?),
+            Code::BOOL => Value::Bool(input.read_bool()?),
+            Code::STRING => Value::String(input.read_string()?),
+            Code::BYTES => Value::Bytes(input.read_bytes()?),
+            Code::CLASS => Value::Class(input.read_uint64()?),
+            Code::ENUMERATION => Value::Enumeration(input.read_sint32()?),
+            Code::EVENT => Value::Event(RPCExtractable::extract_value(&mut input)?),
+            Code::PROCEDURE_CALL => {
+                Value::ProcedureCall(RPCExtractable::extract_value(&mut input)?)
+            }
+            Code::STREAM => Value::Stream(RPCExtractable::extract_value(&mut input)?),
+            Code::STATUS => Value::Status(RPCExtractable::extract_value(&mut input)?),
+            Code::SERVICES => Value::Services(RPCExtractable::extract_value(&mut input)?),
+            Code::TUPLE => {
+                let tuple = krpc::Tuple::parse_from_bytes(bytes)?;
+                if tuple.items.len() != ty.types.len() {
+                    return Err(DecodeError::new(
+                        format!("a tuple of {} items", ty.types.len()),
+                        format!("{} items", tuple.items.len()),
+                    )
+                    .into());
+                }
+                let items = tuple.items.iter().zip(ty.types.iter()).enumerate();
+                Value::Tuple(
+                    items
+                        .map(|(i, (item, ty))| {
+                            Value::decode_nested(ty, item, format_args!(".{}", i))
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            Code::LIST => {
+                let items = read_items(&mut input)?;
+                Value::List(decode_items(type_parameter(ty, 0)?, &items)?)
+            }
+            Code::SET => {
+                let items = read_items(&mut input)?;
+                Value::Set(decode_items(type_parameter(ty, 0)?, &items)?)
+            }
+            Code::DICTIONARY => {
+                let entries = read_items(&mut input)?;
+                let key_type = type_parameter(ty, 0)?;
+                let value_type = type_parameter(ty, 1)?;
+                Value::Dictionary(
+                    entries
+                        .iter()
+                        .enumerate()
+                        .map(|(i, entry)| {
+                            let entry = krpc::DictionaryEntry::parse_from_bytes(entry)?;
+                            Ok((
+                                Value::decode_nested(
+                                    key_type,
+                                    &entry.key,
+                                    format_args!("[{}].key", i),
+                                )?,
+                                Value::decode_nested(
+                                    value_type,
+                                    &entry.value,
+                                    format_args!("[{}].value", i),
+                                )?,
+                            ))
+                        })
+                        .collect::<Result<_, error::RPCError>>()?,
+                )
+            }
+        };
+        Ok(value)
+    }
+
+    /// Encodes the value as the given type. Fails with
+    /// [`RPCError::TypeMismatch`](error::RPCError::TypeMismatch) if the value doesn't have that
+    /// type.
+    pub fn encode(&self, ty: &krpc::Type) -> Result<Vec<u8>, error::RPCError> {
+        use krpc::Type_TypeCode as Code;
+
+        let mut bytes = Vec::new();
+        {
+            let mut output = protobuf::CodedOutputStream::new(&mut bytes);
+            match (ty.code, self) {
+                (Code::CLASS, Value::None) => output.write_uint64_no_tag(0)?,
+                (Code::STRING | Code::BYTES, Value::None) => {}
+                (Code::DOUBLE, Value::Double(v)) => output.write_double_no_tag(*v)?,
+                (Code::FLOAT, Value::Float(v)) => output.write_float_no_tag(*v)?,
+                (Code::SINT32, Value::SInt32(v)) => output.write_sint32_no_tag(*v)?,
+                (Code::SINT64, Value::SInt64(v)) => output.write_sint64_no_tag(*v)?,
+                (Code::UINT32, Value::UInt32(v)) => output.write_uint32_no_tag(*v)?,
+                (Code::UINT64, Value::UInt64(v)) => output.write_uint64_no_tag(*v)?,
+                (Code::BOOL, Value::Bool(v)) => output.write_bool_no_tag(*v)?,
+                (Code::STRING, Value::String(v)) => output.write_string_no_tag(v)?,
+                (Code::BYTES, Value::Bytes(v)) => output.write_bytes_no_tag(v)?,
+                (Code::CLASS, Value::Class(v)) => output.write_uint64_no_tag(*v)?,
+                (Code::ENUMERATION, Value::Enumeration(v)) => output.write_sint32_no_tag(*v)?,
+                (Code::EVENT, Value::Event(m)) => m.encode(&mut output)?,
+                (Code::PROCEDURE_CALL, Value::ProcedureCall(m)) => m.encode(&mut output)?,
+                (Code::STREAM, Value::Stream(m)) => m.encode(&mut output)?,
+                (Code::STATUS, Value::Status(m)) => m.encode(&mut output)?,
+                (Code::SERVICES, Value::Services(m)) => m.encode(&mut output)?,
+                (Code::TUPLE, Value::Tuple(items)) if items.len() == ty.types.len() => {
+                    let mut tuple = krpc::Tuple::new();
+                    for (item, ty) in items.iter().zip(ty.types.iter()) {
+                        tuple.items.push(item.encode(ty)?);
+                    }
+                    tuple.write_to(&mut output)?;
+                }
+                (Code::LIST, Value::List(items)) => {
+                    let mut list = krpc::List::new();
+                    list.items = encode_items(type_parameter(ty, 0)?, items)?;
+                    list.write_to(&mut output)?;
+                }
+                (Code::SET, Value::Set(items)) => {
+                    let mut set = krpc::Set::new();
+                    set.items = encode_items(type_parameter(ty, 0)?, items)?;
+                    set.write_to(&mut output)?;
+                }
+                (Code::DICTIONARY, Value::Dictionary(entries)) => {
+                    let key_type = type_parameter(ty, 0)?;
+                    let value_type = type_parameter(ty, 1)?;
+                    let mut dictionary = krpc::Dictionary::new();
+                    for (key, value) in entries {
+                        let mut entry = krpc::DictionaryEntry::new();
+                        entry.set_key(key.encode(key_type)?);
+                        entry.set_value(value.encode(value_type)?);
+                        dictionary.entries.push(entry);
+                    }
+                    dictionary.write_to(&mut output)?;
+                }
+                _ => {
+                    return Err(error::RPCError::TypeMismatch {
+                        expected: type_name(ty),
+                        found: self.type_name(),
+                    })
+                }
+            }
+            output.flush()?;
+        }
+        Ok(bytes)
+    }
+
+    /// Converts a typed value, encoding it as the given type.
+    pub fn from_typed<T: RPCEncodable>(
+        value: &T,
+        ty: &krpc::Type,
+    ) -> Result<Value, error::RPCError> {
+        Value::decode(ty, &value.encode_to_bytes()?)
+    }
+
+    /// Converts the value to a typed one, encoding it as the given type first.
+    pub fn to_typed<T: RPCExtractable>(&self, ty: &krpc::Type) -> Result<T, error::RPCError> {
+        let bytes = self.encode(ty)?;
+        let mut input = protobuf::CodedInputStream::from_bytes(&bytes);
+        Ok(T::extract_value(&mut input).map_err(decode_error::<T>)?)
+    }
+
+    /// Describes the type of the value, as far as it can be known without a [`krpc::Type`].
+    fn type_name(&self) -> String {
+        let name = match self {
+            Value::None => "NONE",
+            Value::Double(_) => "DOUBLE",
+            Value::Float(_) => "FLOAT",
+            Value::SInt32(_) => "SINT32",
+            Value::SInt64(_) => "SINT64",
+            Value::UInt32(_) => "UINT32",
+            Value::UInt64(_) => "UINT64",
+            Value::Bool(_) => "BOOL",
+            Value::String(_) => "STRING",
+            Value::Bytes(_) => "BYTES",
+            Value::Class(_) => "CLASS",
+            Value::Enumeration(_) => "ENUMERATION",
+            Value::Event(_) => "EVENT",
+            Value::ProcedureCall(_) => "PROCEDURE_CALL",
+            Value::Stream(_) => "STREAM",
+            Value::Status(_) => "STATUS",
+            Value::Services(_) => "SERVICES",
+            Value::Tuple(items) => return format!("TUPLE of {} items", items.len()),
+            Value::List(_) => "LIST",
+            Value::Set(_) => "SET",
+            Value::Dictionary(_) => "DICTIONARY",
+        };
+        name.to_string()
+    }
+}
+
+fn decode_items(ty: &krpc::Type, items: &[Vec<u8>]) -> Result<Vec<Value>, error::RPCError> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| Value::decode_nested(ty, item, format_args!("[{}]", i)))
+        .collect()
+}
+
+fn encode_items(
+    ty: &krpc::Type,
+    items: &[Value],
+) -> Result<protobuf::RepeatedField<Vec<u8>>, error::RPCError> {
+    items.iter().map(|item| item.encode(ty)).collect()
+}
+
+/// Returns the i-th type parameter of a collection type. A type which lacks it is reported as
+/// [`RPCError::Decode`](error::RPCError::Decode).
+fn type_parameter(ty: &krpc::Type, i: usize) -> Result<&krpc::Type, error::RPCError> {
+    ty.types.get(i).ok_or_else(|| {
+        DecodeError::new(format!("a type parameter at index {}", i), type_name(ty)).into()
+    })
+}
+
+/// Describes a type, e.g. `LIST(SpaceCenter.Vessel)`.
+fn type_name(ty: &krpc::Type) -> String {
+    use krpc::Type_TypeCode as Code;
+
+    match ty.code {
+        Code::CLASS | Code::ENUMERATION => format!("{}.{}", ty.service, ty.name),
+        Code::TUPLE | Code::LIST | Code::SET | Code::DICTIONARY => {
+            let parameters: Vec<String> = ty.types.iter().map(type_name).collect();
+            format!("{:?}({})", ty.code, parameters.join(", "))
+        }
+        code => format!("{:?}", code),
+    }
+}
+
+/// Bounds on the data accepted from the server. They protect the client from corrupted or hostile
+/// streams, which could otherwise make it allocate huge buffers or recurse deeply.
+///
+/// Exceeding a limit fails with [`RPCError::LimitExceeded`](error::RPCError::LimitExceeded).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest message read from the server, in bytes.
+    pub max_frame_size: usize,
+    /// Most items in a list or set, or entries in a dictionary.
+    pub max_collection_len: usize,
+    /// Deepest nesting of collections within a value.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_frame_size: 64 * 1024 * 1024,
+            max_collection_len: 1024 * 1024,
+            max_depth: 64,
+        }
+    }
+}
+
+thread_local! {
+    /// The limits checked by extractors on this thread, and the depth of the value being decoded.
+    static DECODING: Cell<(Limits, usize)> = Cell::new((Limits::default(), 0));
+}
+
+/// Runs `f`, which decodes values, with the given limits.
+pub(crate) fn with_limits<R>(limits: &Limits, f: impl FnOnce() -> R) -> R {
+    struct Restore((Limits, usize));
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DECODING.with(|decoding| decoding.set(self.0));
+        }
+    }
+
+    let _restore = Restore(DECODING.with(|decoding| decoding.replace((*limits, 0))));
+    f()
+}
+
+/// Runs `f`, which decodes a value nested in the current one, one level deeper.
+fn nested<R, E: From<LimitError>>(f: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    let (limits, depth) = DECODING.with(Cell::get);
+    if depth >= limits.max_depth {
+        return Err(LimitError {
+            what: "nesting depth",
+            found: depth + 1,
+            max: limits.max_depth,
+        }
+        .into());
+    }
+
+    DECODING.with(|decoding| decoding.set((limits, depth + 1)));
+    let result = f();
+    DECODING.with(|decoding| decoding.set((limits, depth)));
+    result
+}
+
+/// Reads the items of a `krpc::List` or `krpc::Set`, or the entries of a `krpc::Dictionary`, which
+/// are all held in the first field. Past the collection length limit, items are only counted, so
+/// that an oversized collection is rejected without being buffered.
+fn read_items(
+    input: &mut protobuf::CodedInputStream,
+) -> Result<Vec<Vec<u8>>, protobuf::ProtobufError> {
+    let max = DECODING.with(Cell::get).0.max_collection_len;
+    let mut items = Vec::new();
+    let mut len = 0;
+    while !input.eof()? {
+        let (field, wire_type) = input.read_tag_unpack()?;
+        if field != 1 {
+            input.skip_field(wire_type)?;
+            continue;
+        }
+        if wire_type != protobuf::wire_format::WireTypeLengthDelimited {
+            return Err(protobuf::rt::unexpected_wire_type(wire_type));
+        }
+        len += 1;
+        if len > max {
+            input.skip_field(wire_type)?;
+        } else {
+            items.push(input.read_bytes()?);
+        }
+    }
+    check_collection_len(len)?;
+    Ok(items)
+}
+
+/// Checks the number of items of a collection against the current limits.
+fn check_collection_len(len: usize) -> Result<(), LimitError> {
+    let max = DECODING.with(Cell::get).0.max_collection_len;
+    if len > max {
+        Err(LimitError {
+            what: "collection length",
+            found: len,
+            max,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks the size of a message against `max`.
+pub(crate) fn check_frame_size(len: u64, max: usize) -> Result<(), LimitError> {
+    match usize::try_from(len) {
+        Ok(len) if len <= max => Ok(()),
+        _ => Err(LimitError {
+            what: "frame size",
+            found: usize::try_from(len).unwrap_or(usize::MAX),
+            max,
+        }),
+    }
+}
+
+/// Reads length-delimited protobuf messages from a byte stream.
+///
+/// The reader reads the stream in chunks, so it may receive more than one message at a time. Bytes
+/// read past the end of a message are kept for the next call instead of being dropped.
+#[derive(Debug)]
+pub(crate) struct FramedReader {
+    buf: Vec<u8>,
+    /// Largest message accepted, in bytes.
+    max_frame_size: usize,
+}
+
+impl Default for FramedReader {
+    fn default() -> Self {
+        FramedReader {
+            buf: Vec::new(),
+            max_frame_size: Limits::default().max_frame_size,
+        }
+    }
+}
+
+impl FramedReader {
+    /// Sets the largest message accepted, in bytes.
+    pub(crate) fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// Reads the next message, waiting for more bytes from `sock` if needed, and returns its
+    /// encoded bytes.
+    pub(crate) fn read_frame(
+        &mut self,
+        sock: &mut dyn Read,
+    ) -> Result<Vec<u8>, protobuf::ProtobufError> {
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return Ok(frame);
+            }
+
+            let mut chunk = [0; 4096];
+            let n = match sock.read(&mut chunk) {
+                Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            self.extend(&chunk[..n]);
+        }
+    }
+
+    /// Reads the next message from an asynchronous source, like [`FramedReader::read_frame`].
+    ///
+    /// The future is cancel-safe: bytes read before it is dropped are kept for the next call.
+    #[cfg(feature = "async")]
+    pub(crate) async fn read_frame_async<R>(
+        &mut self,
+        sock: &mut R,
+    ) -> Result<Vec<u8>, protobuf::ProtobufError>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        use tokio::io::AsyncReadExt;
+
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return Ok(frame);
+            }
+
+            let mut chunk = [0; 4096];
+            match sock.read(&mut chunk).await? {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+                n => self.extend(&chunk[..n]),
+            }
+        }
+    }
+
+    /// Adds bytes received from the stream.
+    pub(crate) fn extend(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Returns `true` if no bytes are waiting to be decoded.
+    #[cfg(feature = "async")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Decodes the first message from the bytes received so far. Returns `None` if they don't hold
+    /// a complete message yet.
+    #[cfg(feature = "async")]
+    pub(crate) fn next_message<M>(&mut self) -> Result<Option<M>, protobuf::ProtobufError>
+    where
+        M: protobuf::Message,
+    {
+        match self.next_frame()? {
+            Some(frame) => M::parse_from_bytes(&frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes the bytes of the first message out of the bytes received so far. Returns `None` if
+    /// they don't hold a complete message yet.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, protobuf::ProtobufError> {
+        let mut len = 0u64;
+        let mut header = 0;
+        loop {
+            let byte = match self.buf.get(header) {
+                Some(byte) => *byte,
+                None => return Ok(None),
+            };
+            len |= u64::from(byte & 0x7f) << (7 * header);
+            header += 1;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if header >= 10 {
+                return Err(protobuf::ProtobufError::WireError(
+                    protobuf::error::WireError::IncorrectVarint,
+                ));
+            }
+        }
+        check_frame_size(len, self.max_frame_size)?;
+
+        let end = match usize::try_from(len)
+            .ok()
+            .and_then(|len| header.checked_add(len))
+        {
+            Some(end) if end <= self.buf.len() => end,
+            _ => return Ok(None),
+        };
+
+        let frame = self.buf[header..end].to_vec();
+        self.buf.drain(..end);
+        Ok(Some(frame))
+    }
+}
+
+/// Writes a length-delimited protobuf message to an asynchronous sink.
+#[cfg(feature = "async")]
+pub(crate) async fn write_message_async<M, W>(
+    msg: &M,
+    sock: &mut W,
+) -> Result<(), protobuf::ProtobufError>
 where
     M: protobuf::Message,
+    W: tokio::io::AsyncWrite + Unpin,
 {
-    let mut input_stream = protobuf::CodedInputStream::new(sock);
-    input_stream.read_message()
+    use tokio::io::AsyncWriteExt;
+
+    let bytes = msg.write_length_delimited_to_bytes()?;
+    sock.write_all(&bytes).await?;
+    Ok(())
 }
 
-/// Extracts the result from a [`krpc::ProcedureResult`]
-pub(crate) fn extract_result<T>(proc_result: &krpc::ProcedureResult) -> Result<T, error::RPCError>
+/// Extracts the value of a procedure result, or the error raised by the procedure. Values which
+/// can't be decoded are reported as [`RPCError::Decode`](error::RPCError::Decode).
+pub fn extract_result<T>(proc_result: &krpc::ProcedureResult) -> Result<T, error::RPCError>
 where
     T: RPCExtractable,
 {
@@ -427,7 +1149,123 @@ where
         ))
     } else {
         let mut input = protobuf::CodedInputStream::from_bytes(proc_result.get_value());
-        let res = RPCExtractable::extract_value(&mut input)?;
+        let res = T::extract_value(&mut input).map_err(decode_error::<T>)?;
         Ok(res)
     }
 }
+
+/// Describes a value which couldn't be decoded.
+///
+/// Extractors can only return a `protobuf::ProtobufError`, so the error travels inside an
+/// `io::Error` until it is turned into an [`RPCError::Decode`](error::RPCError::Decode).
+#[derive(Debug)]
+pub(crate) struct DecodeError {
+    pub(crate) expected: String,
+    pub(crate) found: String,
+    /// Where the value is within the decoded one, e.g. `.1[3]` for the fourth item of a list in
+    /// the second item of a tuple.
+    pub(crate) path: String,
+}
+
+impl DecodeError {
+    pub(crate) fn new(expected: String, found: String) -> Self {
+        DecodeError {
+            expected,
+            found,
+            path: String::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "expected {} at value{}, found {}",
+            self.expected, self.path, self.found
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for protobuf::ProtobufError {
+    fn from(e: DecodeError) -> Self {
+        protobuf::ProtobufError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// A limit of [`Limits`] exceeded while reading a message. It travels inside an `io::Error` like
+/// [`DecodeError`].
+#[derive(Debug)]
+pub(crate) struct LimitError {
+    pub(crate) what: &'static str,
+    pub(crate) found: usize,
+    pub(crate) max: usize,
+}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the {} of {} exceeds the limit of {}",
+            self.what, self.found, self.max
+        )
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl From<LimitError> for protobuf::ProtobufError {
+    fn from(e: LimitError) -> Self {
+        protobuf::ProtobufError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Takes an error back from a `protobuf::ProtobufError`, if it carries one of type `E`.
+pub(crate) fn take_error<E>(e: protobuf::ProtobufError) -> Result<E, protobuf::ProtobufError>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match e {
+        protobuf::ProtobufError::IoError(e) if e.get_ref().is_some_and(|e| e.is::<E>()) => {
+            Ok(*e.into_inner().unwrap().downcast::<E>().unwrap())
+        }
+        e => Err(e),
+    }
+}
+
+/// Turns any error raised while extracting a `T` into a [`DecodeError`], except for exceeded
+/// limits which are passed through.
+fn decode_error<T>(e: protobuf::ProtobufError) -> protobuf::ProtobufError {
+    match e {
+        protobuf::ProtobufError::IoError(ref io)
+            if io
+                .get_ref()
+                .is_some_and(|e| e.is::<DecodeError>() || e.is::<LimitError>()) =>
+        {
+            e
+        }
+        e => DecodeError::new(std::any::type_name::<T>().to_string(), e.to_string()).into(),
+    }
+}
+
+/// Extracts a value nested in another one. Errors record the position of the value, given by
+/// `segment`.
+fn extract_nested<T: RPCExtractable>(
+    bytes: &[u8],
+    segment: std::fmt::Arguments,
+) -> Result<T, protobuf::ProtobufError> {
+    nested(|| {
+        let mut input = protobuf::CodedInputStream::from_bytes(bytes);
+        T::extract_value(&mut input).map_err(|e| {
+            match take_error::<DecodeError>(decode_error::<T>(e)) {
+                Ok(mut e) => {
+                    e.path.insert_str(0, &segment.to_string());
+                    e.into()
+                }
+                Err(e) => e,
+            }
+        })
+    })
+}
@@ -0,0 +1,162 @@
+//! A connection to both the RPC and Stream servers.
+use crate::builder::ClientBuilder;
+use crate::client::{CallHandle, RPCClient, RPCRequest, RPCResponse};
+use crate::codec;
+use crate::error;
+use crate::stream::{
+    stream_change, StreamChange, StreamClient, StreamHandle, StreamID, StreamUpdate,
+};
+use crate::transport::{Framed, Transport};
+
+use std::collections::HashSet;
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// Owns an [`RPCClient`] and the [`StreamClient`] associated with it, and keeps track of the
+/// streams created through it.
+///
+/// # Example:
+/// ```rust,ignore
+///let mut conn = krpc_mars::Connection::connect("Example", "127.0.0.1:50000", "127.0.0.1:50001")?;
+///let ut = conn.add_stream(&space_center::get_ut())?;
+///let vessel = conn.call(&space_center::get_active_vessel())?;
+///
+///let update = conn.next_update()?;
+///println!("ut: {:?}", update.get_result(&ut)?);
+///
+///conn.close()?;
+/// ```
+#[derive(Debug)]
+pub struct Connection<T: Transport = Framed<TcpStream>> {
+    client: RPCClient<T>,
+    stream_client: StreamClient<T>,
+    streams: HashSet<StreamID>,
+}
+
+impl Connection {
+    /// Connects to the RPC and Stream servers. The client will show up in the KRPC UI with the
+    /// given client name.
+    pub fn connect<A: ToSocketAddrs, B: ToSocketAddrs>(
+        client_name: &str,
+        rpc_addr: A,
+        stream_addr: B,
+    ) -> Result<Self, error::ConnectionError> {
+        let client = RPCClient::connect(client_name, rpc_addr)?;
+        let stream_client = StreamClient::connect(&client, stream_addr)?;
+        Ok(Self::from_clients(client, stream_client))
+    }
+
+    /// Connects to the RPC and Stream servers configured in `builder`.
+    pub fn with_builder(builder: &ClientBuilder) -> Result<Self, error::ConnectionError> {
+        let client = builder.connect()?;
+        let stream_client = builder.connect_stream(&client)?;
+        Ok(Self::from_clients(client, stream_client))
+    }
+}
+
+impl<T: Transport> Connection<T> {
+    /// Groups an RPC client with the stream client created for it.
+    pub fn from_clients(client: RPCClient<T>, stream_client: StreamClient<T>) -> Self {
+        Connection {
+            client,
+            stream_client,
+            streams: HashSet::new(),
+        }
+    }
+
+    /// Sends a single RPC request to the server.
+    pub fn call<R: codec::RPCExtractable>(
+        &mut self,
+        call: &CallHandle<R>,
+    ) -> Result<R, error::RPCError> {
+        let (result,) = crate::batch_call!(self, (call))?;
+        result
+    }
+
+    /// Sends an [`RPCRequest`] to the server. A single RPCRequest may contain multiple RPC calls.
+    pub fn batch(&mut self, request: RPCRequest) -> Result<RPCResponse, error::RPCError> {
+        let calls = request.calls.clone();
+        let response = self.client.submit_request(request)?;
+
+        for (call, result) in calls.iter().zip(response.results.iter()) {
+            match stream_change(call, result) {
+                Some(StreamChange::Added(id)) => {
+                    self.streams.insert(id);
+                }
+                Some(StreamChange::Removed(id)) => {
+                    self.streams.remove(&id);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Same as [`Connection::batch`]. This allows using the connection with
+    /// [`batch_call!`](crate::batch_call) and [`batch_call_unwrap!`](crate::batch_call_unwrap).
+    pub fn submit_request(&mut self, request: RPCRequest) -> Result<RPCResponse, error::RPCError> {
+        self.batch(request)
+    }
+
+    /// Creates a stream for the given call.
+    pub fn add_stream<R: codec::RPCExtractable>(
+        &mut self,
+        call: &CallHandle<R>,
+    ) -> Result<StreamHandle<R>, error::RPCError> {
+        self.call(&call.to_stream())
+    }
+
+    /// Removes a stream.
+    pub fn remove_stream<R>(&mut self, handle: StreamHandle<R>) -> Result<(), error::RPCError> {
+        self.call(&handle.remove())
+    }
+
+    /// Waits for the next stream update.
+    pub fn next_update(&mut self) -> Result<StreamUpdate, error::RPCError> {
+        self.stream_client.recv_update()
+    }
+
+    /// Removes all the streams created through this connection and closes it. Dropping the
+    /// connection does the same, but ignores errors.
+    pub fn close(mut self) -> Result<(), error::RPCError> {
+        self.remove_streams()
+    }
+
+    fn remove_streams(&mut self) -> Result<(), error::RPCError> {
+        let ids: Vec<StreamID> = std::mem::take(&mut self.streams).into_iter().collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let handles: Vec<CallHandle<()>> = ids
+            .into_iter()
+            .map(|id| StreamHandle::<()>::new(id).remove())
+            .collect();
+        let mut request = RPCRequest::default();
+        for handle in &handles {
+            request.add_call(handle);
+        }
+
+        let response = self.client.submit_request(request)?;
+        for (idx, handle) in handles.iter().enumerate() {
+            handle.get_result(&response, idx)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the RPC client of this connection.
+    pub fn client(&mut self) -> &mut RPCClient<T> {
+        &mut self.client
+    }
+
+    /// Returns the stream client of this connection.
+    pub fn stream_client(&mut self) -> &mut StreamClient<T> {
+        &mut self.stream_client
+    }
+}
+
+impl<T: Transport> Drop for Connection<T> {
+    fn drop(&mut self) {
+        let _ = self.remove_streams();
+    }
+}
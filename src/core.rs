@@ -0,0 +1,158 @@
+//! Bindings for the procedures of the core `KRPC` service.
+//!
+//! Like the bindings generated by krpc-mars-terraformer for the other services, each function
+//! returns a [`CallHandle`] to be passed to a client.
+use crate::client::CallHandle;
+use crate::codec;
+use crate::event::{EventHandle, Expression};
+use crate::krpc;
+use crate::stream::StreamHandle;
+
+use codec::RPCEncodable;
+
+/// The identifier the server assigned to a client.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClientID(pub Vec<u8>);
+
+impl codec::RPCExtractable for ClientID {
+    fn extract_value(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Self, protobuf::ProtobufError> {
+        Ok(ClientID(input.read_bytes()?))
+    }
+}
+
+/// The game scenes, as returned by [`get_current_game_scene`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GameScene {
+    SpaceCenter,
+    Flight,
+    TrackingStation,
+    EditorVAB,
+    EditorSPH,
+    MissionBuilder,
+}
+
+impl codec::RPCExtractable for GameScene {
+    fn extract_value(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Self, protobuf::ProtobufError> {
+        match input.read_sint32()? {
+            0 => Ok(GameScene::SpaceCenter),
+            1 => Ok(GameScene::Flight),
+            2 => Ok(GameScene::TrackingStation),
+            3 => Ok(GameScene::EditorVAB),
+            4 => Ok(GameScene::EditorSPH),
+            5 => Ok(GameScene::MissionBuilder),
+            v => Err(protobuf::ProtobufError::WireError(
+                protobuf::error::WireError::InvalidEnumValue(v),
+            )),
+        }
+    }
+}
+
+/// Returns some information about the server, such as its version.
+pub fn get_status() -> CallHandle<krpc::Status> {
+    CallHandle::new(krpc_call("GetStatus", vec![]))
+}
+
+/// Returns the services, procedures, classes and enumerations provided by the server.
+pub fn get_services() -> CallHandle<krpc::Services> {
+    CallHandle::new(krpc_call("GetServices", vec![]))
+}
+
+/// Returns the identifier of the calling client.
+pub fn get_client_id() -> CallHandle<ClientID> {
+    CallHandle::new(krpc_call("GetClientID", vec![]))
+}
+
+/// Returns the name of the calling client.
+pub fn get_client_name() -> CallHandle<String> {
+    CallHandle::new(krpc_call("GetClientName", vec![]))
+}
+
+/// Returns the identifier, name and address of every client connected to the server.
+pub fn get_clients() -> CallHandle<Vec<(ClientID, String, String)>> {
+    CallHandle::new(krpc_call("get_Clients", vec![]))
+}
+
+/// Returns the current game scene.
+pub fn get_current_game_scene() -> CallHandle<GameScene> {
+    CallHandle::new(krpc_call("get_CurrentGameScene", vec![]))
+}
+
+/// Returns whether the game is paused.
+pub fn get_paused() -> CallHandle<bool> {
+    CallHandle::new(krpc_call("get_Paused", vec![]))
+}
+
+/// Pauses or unpauses the game.
+pub fn set_paused(value: bool) -> CallHandle<()> {
+    CallHandle::new(krpc_call(
+        "set_Paused",
+        vec![value.encode_to_bytes().unwrap()],
+    ))
+}
+
+/// Adds a stream sending the results of the given call. If `start` is false, the stream doesn't
+/// send updates until [`start_stream`] is called.
+pub fn add_stream<T: codec::RPCExtractable>(
+    call: &CallHandle<T>,
+    start: bool,
+) -> CallHandle<StreamHandle<T>> {
+    let mut args = vec![call.get_call().encode_to_bytes().unwrap()];
+    // Older servers don't know about the `start` argument, so it is only sent when needed.
+    if !start {
+        args.push(start.encode_to_bytes().unwrap());
+    }
+    CallHandle::new(krpc_call("AddStream", args))
+}
+
+/// Adds an event triggered by the given expression, which must evaluate to a `bool`.
+pub fn add_event(expression: &Expression) -> CallHandle<EventHandle> {
+    CallHandle::new(krpc_call(
+        "AddEvent",
+        vec![expression.encode_to_bytes().unwrap()],
+    ))
+}
+
+/// Starts a stream created paused.
+pub fn start_stream<T>(stream: StreamHandle<T>) -> CallHandle<()> {
+    CallHandle::new(krpc_call("StartStream", vec![stream_id(stream)]))
+}
+
+/// Limits the update rate of a stream to `rate` updates per second. A rate of 0 removes the
+/// limit.
+pub fn set_stream_rate<T>(stream: StreamHandle<T>, rate: f32) -> CallHandle<()> {
+    CallHandle::new(krpc_call(
+        "SetStreamRate",
+        vec![stream_id(stream), rate.encode_to_bytes().unwrap()],
+    ))
+}
+
+/// Removes a stream.
+pub fn remove_stream<T>(stream: StreamHandle<T>) -> CallHandle<()> {
+    CallHandle::new(krpc_call("RemoveStream", vec![stream_id(stream)]))
+}
+
+fn stream_id<T>(stream: StreamHandle<T>) -> Vec<u8> {
+    stream.stream_id.encode_to_bytes().unwrap()
+}
+
+/// Builds a call to a procedure of the KRPC service. Arguments are given in order, already
+/// encoded.
+pub(crate) fn krpc_call(procedure: &str, args: Vec<Vec<u8>>) -> krpc::ProcedureCall {
+    let mut arguments = protobuf::RepeatedField::<krpc::Argument>::new();
+    for (position, value) in args.into_iter().enumerate() {
+        let mut arg = krpc::Argument::new();
+        arg.set_position(position as u32);
+        arg.set_value(value);
+        arguments.push(arg);
+    }
+
+    let mut proc_call = krpc::ProcedureCall::new();
+    proc_call.set_service(String::from("KRPC"));
+    proc_call.set_procedure(String::from(procedure));
+    proc_call.set_arguments(arguments);
+    proc_call
+}
@@ -0,0 +1,171 @@
+//! Per-stream channels fed by a background thread.
+use crate::codec;
+use crate::error;
+use crate::krpc;
+use crate::stream::{StreamClient, StreamHandle, StreamID, StreamUpdate};
+use crate::transport::Transport;
+use crate::worker::Worker;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Receives stream updates on a background thread and sends each new value to the subscribers of
+/// its stream. Dropping the dispatcher stops the thread.
+///
+/// # Example:
+/// ```rust,ignore
+///let ut_stream_handle = client.mk_call(&space_center::get_ut().to_stream())?;
+///let dispatcher = krpc_mars::StreamDispatcher::spawn(stream_client);
+///
+///let ut = dispatcher.subscribe(ut_stream_handle);
+///std::thread::spawn(move || {
+///    for value in ut {
+///        println!("ut: {}", value.unwrap());
+///    }
+///});
+/// ```
+#[derive(Debug)]
+pub struct StreamDispatcher {
+    state: Arc<Mutex<State>>,
+    receiver: Worker,
+}
+
+#[derive(Default)]
+struct State {
+    /// Subscribers of each stream, grouped by value type.
+    subscribers: HashMap<StreamID, Vec<Box<dyn Dispatch>>>,
+    /// Latest result of each stream, for new subscribers.
+    latest: HashMap<StreamID, krpc::ProcedureResult>,
+    /// Limits of the stream client, checked when extracting values.
+    limits: codec::Limits,
+    /// Set once the receiver thread stopped. No subscriber is accepted after that.
+    closed: bool,
+}
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("State")
+            .field("streams", &self.subscribers.keys())
+            .finish()
+    }
+}
+
+/// The subscribers of a stream which expect the same value type.
+trait Dispatch: Send {
+    /// Sends the value to all subscribers. Returns `false` if none of them is listening anymore.
+    fn dispatch(&mut self, result: &krpc::ProcedureResult) -> bool;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct Subscribers<T> {
+    senders: Vec<Sender<Result<T, error::RPCError>>>,
+}
+
+impl<T> Dispatch for Subscribers<T>
+where
+    T: codec::RPCExtractable + Clone + Send + 'static,
+{
+    fn dispatch(&mut self, result: &krpc::ProcedureResult) -> bool {
+        match codec::extract_result::<T>(result) {
+            Ok(value) => self.senders.retain(|s| s.send(Ok(value.clone())).is_ok()),
+            // Errors can't be cloned, so each subscriber gets its own.
+            Err(_) => self
+                .senders
+                .retain(|s| s.send(codec::extract_result(result)).is_ok()),
+        }
+        !self.senders.is_empty()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl StreamDispatcher {
+    /// Moves the stream client to a new thread which dispatches updates until the connection
+    /// fails. When that happens, all subscriptions are closed.
+    pub fn spawn<T>(stream_client: StreamClient<T>) -> Self
+    where
+        T: Transport + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State::default()));
+        let on_update = {
+            let state = Arc::clone(&state);
+            move |update: StreamUpdate| {
+                let mut state = state.lock().unwrap();
+                state.limits = update.limits;
+                for (id, result) in update.updates {
+                    if let Some(subscribers) = state.subscribers.get_mut(&id) {
+                        codec::with_limits(&update.limits, || {
+                            subscribers.retain_mut(|s| s.dispatch(&result))
+                        });
+                    }
+                    state.latest.insert(id, result);
+                }
+            }
+        };
+        let on_stop = {
+            let state = Arc::clone(&state);
+            move || {
+                // Dropping the senders closes the subscriptions.
+                let mut state = state.lock().unwrap();
+                state.closed = true;
+                state.subscribers.clear();
+            }
+        };
+
+        StreamDispatcher {
+            state,
+            receiver: Worker::spawn(stream_client, on_update, on_stop),
+        }
+    }
+
+    /// Subscribes to a stream. The returned channel receives the current value of the stream, if
+    /// any, then every new value. A value which fails to decode is reported as an error to the
+    /// subscribers of that stream only.
+    pub fn subscribe<T>(&self, handle: StreamHandle<T>) -> Receiver<Result<T, error::RPCError>>
+    where
+        T: codec::RPCExtractable + Clone + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            // Dropping the sender closes the subscription right away.
+            return receiver;
+        }
+
+        if let Some(result) = state.latest.get(&handle.stream_id) {
+            let _ = sender.send(codec::with_limits(&state.limits, || {
+                codec::extract_result(result)
+            }));
+        }
+
+        let subscribers = state.subscribers.entry(handle.stream_id).or_default();
+        let group = subscribers
+            .iter_mut()
+            .find_map(|s| s.as_any_mut().downcast_mut::<Subscribers<T>>());
+        match group {
+            Some(group) => group.senders.push(sender),
+            None => subscribers.push(Box::new(Subscribers {
+                senders: vec![sender],
+            })),
+        }
+
+        receiver
+    }
+
+    /// Returns `false` once the receiver thread has stopped. [`StreamDispatcher::take_error`]
+    /// tells why it stopped.
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_running()
+    }
+
+    /// Returns the error which stopped the receiver thread, if it stopped. The error is only
+    /// returned once.
+    pub fn take_error(&self) -> Option<error::RPCError> {
+        self.receiver.take_error()
+    }
+}
@@ -0,0 +1,170 @@
+//! Calls to procedures for which no bindings were generated.
+use crate::client::{RPCClient, RPCRequest};
+use crate::codec::{self, Value};
+use crate::error;
+use crate::krpc;
+use crate::transport::Transport;
+
+use std::collections::HashMap;
+
+/// The procedures provided by the server, as returned by `KRPC.GetServices`.
+#[derive(Debug)]
+pub(crate) struct Schema {
+    /// Procedures by service name and procedure name.
+    services: HashMap<String, HashMap<String, krpc::Procedure>>,
+}
+
+impl Schema {
+    fn new(services: krpc::Services) -> Self {
+        let services = services
+            .services
+            .into_iter()
+            .map(|service| {
+                let procedures = service
+                    .procedures
+                    .into_iter()
+                    .map(|procedure| (procedure.name.clone(), procedure))
+                    .collect();
+                (service.name, procedures)
+            })
+            .collect();
+        Schema { services }
+    }
+
+    fn procedure(&self, service: &str, procedure: &str) -> Option<&krpc::Procedure> {
+        self.services.get(service)?.get(procedure)
+    }
+}
+
+/// Returns the default value of a parameter, if it has one. The schema can't tell an empty
+/// default value from no default at all, so an empty one is taken as the null default of the
+/// types which have one: strings, bytes and objects.
+pub(crate) fn default_value(parameter: &krpc::Parameter) -> Option<Vec<u8>> {
+    let default = parameter.get_default_value();
+    if !default.is_empty() {
+        return Some(default.to_vec());
+    }
+    Value::None.encode(parameter.get_field_type()).ok()
+}
+
+impl<T: Transport> RPCClient<T> {
+    /// Calls a procedure by name. The arguments are checked against the parameters of the
+    /// procedure, and the result is decoded according to its return type. Trailing arguments may
+    /// be left out if their parameters have a default value.
+    ///
+    /// An argument which doesn't have the type of its parameter is reported as
+    /// [`RPCError::TypeMismatch`](error::RPCError::TypeMismatch); a wrong number of arguments as
+    /// [`RPCError::InvalidArgument`](error::RPCError::InvalidArgument).
+    ///
+    /// The procedures provided by the server are fetched with `KRPC.GetServices` on the first
+    /// call, and kept for the next ones.
+    ///
+    /// # Example:
+    /// ```rust,ignore
+    ///use krpc_mars::codec::Value;
+    ///
+    ///let ut = client.call_dynamic("SpaceCenter", "get_UT", &[])?;
+    ///if let Value::Double(ut) = ut {
+    ///    println!("ut: {}", ut);
+    ///}
+    /// ```
+    pub fn call_dynamic(
+        &mut self,
+        service: &str,
+        procedure: &str,
+        args: &[Value],
+    ) -> Result<Value, error::RPCError> {
+        let definition = self.procedure(service, procedure)?.clone();
+
+        let invalid_argument = |position, reason: String| error::RPCError::InvalidArgument {
+            procedure: format!("{}.{}", service, procedure),
+            position,
+            reason,
+        };
+        if args.len() > definition.parameters.len() {
+            return Err(invalid_argument(
+                definition.parameters.len(),
+                format!(
+                    "the procedure takes {} arguments",
+                    definition.parameters.len()
+                ),
+            ));
+        }
+
+        let mut proc_call = krpc::ProcedureCall::new();
+        proc_call.set_service(service.to_string());
+        proc_call.set_procedure(procedure.to_string());
+        for (position, parameter) in definition.parameters.iter().enumerate() {
+            let value = match args.get(position) {
+                Some(value) => value
+                    .encode(parameter.get_field_type())
+                    .map_err(|e| match e {
+                        e @ error::RPCError::TypeMismatch { .. } => e,
+                        e => invalid_argument(position, e.to_string()),
+                    })?,
+                None => match default_value(parameter) {
+                    Some(default) => default,
+                    None => {
+                        return Err(invalid_argument(
+                            position,
+                            format!("missing argument {}", parameter.name),
+                        ))
+                    }
+                },
+            };
+            let mut arg = krpc::Argument::new();
+            arg.set_position(position as u32);
+            arg.set_value(value);
+            proc_call.mut_arguments().push(arg);
+        }
+
+        let mut request = RPCRequest::default();
+        request.calls.push(proc_call);
+        let mut response = self.submit_request(request)?;
+
+        let mut result = response.results.pop().ok_or_else(|| {
+            codec::DecodeError::new("a result at index 0".into(), "0 results".into())
+        })?;
+        if result.has_error() {
+            return Err(error::RPCError::KRPCRequestErr(result.take_error()));
+        }
+        let nullable = definition.return_is_nullable;
+        if !definition.has_return_type() || (nullable && result.get_value().is_empty()) {
+            return Ok(Value::None);
+        }
+        let value = codec::with_limits(&response.limits, || {
+            Value::decode(definition.get_return_type(), result.get_value())
+        })?;
+        match value {
+            Value::Class(0) if nullable => Ok(Value::None),
+            value => Ok(value),
+        }
+    }
+
+    /// Looks a procedure up in the schema of the server. The schema is fetched with
+    /// `KRPC.GetServices` on the first call, and kept for the next ones.
+    ///
+    /// # Example:
+    /// ```rust,ignore
+    ///let procedure = client.procedure("SpaceCenter", "Vessel_Flight")?.clone();
+    ///let call = vessel.flight(reference_frame).without_arg(1).with_defaults(&procedure);
+    ///let flight = client.mk_call(&call)?;
+    /// ```
+    pub fn procedure(
+        &mut self,
+        service: &str,
+        procedure: &str,
+    ) -> Result<&krpc::Procedure, error::RPCError> {
+        if self.schema.is_none() {
+            let services = self.mk_call(&crate::core::get_services())?;
+            self.schema = Some(Schema::new(services));
+        }
+        self.schema
+            .as_ref()
+            .and_then(|schema| schema.procedure(service, procedure))
+            .ok_or_else(|| error::RPCError::UnknownProcedure {
+                service: service.to_string(),
+                procedure: procedure.to_string(),
+            })
+    }
+}
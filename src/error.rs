@@ -5,11 +5,20 @@ use crate::krpc;
 pub enum ConnectionError {
     /// Could not connect to the server
     #[error(transparent)]
-    ConnectionFailed(#[from] std::io::Error),
+    ConnectionFailed(std::io::Error),
+
+    /// The server didn't answer in time
+    #[error("Timed out while connecting to the server")]
+    Timeout,
 
     /// Protobuf error when doing the handshake
     #[error(transparent)]
-    ProtobufErr(#[from] protobuf::ProtobufError),
+    ProtobufErr(protobuf::ProtobufError),
+
+    /// WebSocket error when connecting or doing the handshake
+    #[cfg(feature = "websocket")]
+    #[error(transparent)]
+    WebSocketErr(Box<tungstenite::Error>),
 
     /// Server refused the connection
     #[error("Connection refused by the server (status {status:?}): {error}")]
@@ -17,6 +26,10 @@ pub enum ConnectionError {
         error: String,
         status: krpc::ConnectionResponse_Status,
     },
+
+    /// An RPC needed to set up the connection failed
+    #[error("RPC failed while setting up the connection: {0}")]
+    RPCFailed(#[source] Box<RPCError>),
 }
 
 /// Errors that can occur when performing an RPC.
@@ -24,7 +37,10 @@ pub enum ConnectionError {
 pub enum RPCError {
     /// IO Error while sending/reading
     #[error(transparent)]
-    IOErr(#[from] std::io::Error),
+    IOErr(std::io::Error),
+    /// The server didn't answer in time
+    #[error("Timed out while waiting for the server")]
+    Timeout,
     /// An error raised by the kRPC mod
     #[error(
         "The RPC request failed: service={} procedure={} description={}",
@@ -33,5 +49,170 @@ pub enum RPCError {
     KRPCRequestErr(krpc::Error),
     /// Some protobuf error on the request/response level
     #[error(transparent)]
-    ProtobufErr(#[from] protobuf::ProtobufError),
+    ProtobufErr(protobuf::ProtobufError),
+    /// WebSocket error while sending/reading
+    #[cfg(feature = "websocket")]
+    #[error(transparent)]
+    WebSocketErr(Box<tungstenite::Error>),
+    /// The connection was lost and could not be established again
+    #[error("Could not reconnect to the server: {0}")]
+    ReconnectFailed(#[source] ConnectionError),
+    /// The stream could not be created again after the
+    /// [`ReconnectingClient`](crate::ReconnectingClient) reconnected, so it can't be started or
+    /// given a rate
+    #[error("Stream {0} was not restored after the last reconnection")]
+    StreamNotRestored(u64),
+    /// The server doesn't provide the procedure called with
+    /// [`RPCClient::call_dynamic`](crate::RPCClient::call_dynamic)
+    #[error("Unknown procedure {service}.{procedure}")]
+    UnknownProcedure { service: String, procedure: String },
+    /// An argument given to [`RPCClient::call_dynamic`](crate::RPCClient::call_dynamic) doesn't
+    /// match the parameters of the procedure
+    #[error("Invalid argument {position} of {procedure}: {reason}")]
+    InvalidArgument {
+        procedure: String,
+        position: usize,
+        reason: String,
+    },
+    /// A value received from the server could not be decoded. `path` tells where the faulty
+    /// value is within the decoded one, e.g. `.1[3]` for the fourth item of a list in the second
+    /// item of a tuple.
+    #[error("Could not decode the value: expected {expected} at value{path}, found {found}")]
+    Decode {
+        expected: String,
+        found: String,
+        path: String,
+    },
+    /// A [`Value`](crate::codec::Value) doesn't have the type it is encoded as
+    #[error("Expected a value of type {expected}, found {found}")]
+    TypeMismatch { expected: String, found: String },
+    /// The ticket given to [`RPCClient::recv_response`](crate::RPCClient::recv_response) was
+    /// issued by another client
+    #[error("Unknown or already-consumed ticket")]
+    UnknownTicket,
+    /// The server sent more data than allowed by the [`Limits`](crate::codec::Limits) of the
+    /// client. `what` names the limit, e.g. `frame size`.
+    #[error("The {what} of {found} exceeds the limit of {max}")]
+    LimitExceeded {
+        what: &'static str,
+        found: usize,
+        max: usize,
+    },
+}
+
+impl RPCError {
+    /// Whether the server didn't answer in time.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, RPCError::Timeout)
+    }
+}
+
+/// Read timeouts are reported as `WouldBlock` on unix and `TimedOut` on windows.
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+    )
+}
+
+impl From<std::io::Error> for ConnectionError {
+    fn from(e: std::io::Error) -> Self {
+        if is_timeout(&e) {
+            ConnectionError::Timeout
+        } else {
+            ConnectionError::ConnectionFailed(e)
+        }
+    }
+}
+
+impl From<protobuf::ProtobufError> for ConnectionError {
+    fn from(e: protobuf::ProtobufError) -> Self {
+        #[cfg(feature = "websocket")]
+        let e = match crate::codec::take_error::<tungstenite::Error>(e) {
+            Ok(e) => return e.into(),
+            Err(e) => e,
+        };
+        match e {
+            protobuf::ProtobufError::IoError(e) if is_timeout(&e) => ConnectionError::Timeout,
+            e => ConnectionError::ProtobufErr(e),
+        }
+    }
+}
+
+impl From<RPCError> for ConnectionError {
+    fn from(e: RPCError) -> Self {
+        match e {
+            RPCError::IOErr(e) => ConnectionError::ConnectionFailed(e),
+            RPCError::Timeout => ConnectionError::Timeout,
+            RPCError::ProtobufErr(e) => ConnectionError::ProtobufErr(e),
+            #[cfg(feature = "websocket")]
+            RPCError::WebSocketErr(e) => ConnectionError::WebSocketErr(e),
+            e => ConnectionError::RPCFailed(Box::new(e)),
+        }
+    }
+}
+
+impl From<std::io::Error> for RPCError {
+    fn from(e: std::io::Error) -> Self {
+        if is_timeout(&e) {
+            RPCError::Timeout
+        } else {
+            RPCError::IOErr(e)
+        }
+    }
+}
+
+impl From<protobuf::ProtobufError> for RPCError {
+    fn from(e: protobuf::ProtobufError) -> Self {
+        use crate::codec::{take_error, DecodeError, LimitError};
+
+        let e = match take_error::<DecodeError>(e) {
+            Ok(e) => return e.into(),
+            Err(e) => e,
+        };
+        #[cfg(feature = "websocket")]
+        let e = match take_error::<tungstenite::Error>(e) {
+            Ok(e) => return e.into(),
+            Err(e) => e,
+        };
+        match take_error::<LimitError>(e) {
+            Ok(e) => e.into(),
+            Err(protobuf::ProtobufError::IoError(e)) if is_timeout(&e) => RPCError::Timeout,
+            Err(e) => RPCError::ProtobufErr(e),
+        }
+    }
+}
+
+impl From<crate::codec::LimitError> for RPCError {
+    fn from(e: crate::codec::LimitError) -> Self {
+        RPCError::LimitExceeded {
+            what: e.what,
+            found: e.found,
+            max: e.max,
+        }
+    }
+}
+
+impl From<crate::codec::DecodeError> for RPCError {
+    fn from(e: crate::codec::DecodeError) -> Self {
+        RPCError::Decode {
+            expected: e.expected,
+            found: e.found,
+            path: e.path,
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl From<tungstenite::Error> for ConnectionError {
+    fn from(e: tungstenite::Error) -> Self {
+        ConnectionError::WebSocketErr(Box::new(e))
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl From<tungstenite::Error> for RPCError {
+    fn from(e: tungstenite::Error) -> Self {
+        RPCError::WebSocketErr(Box::new(e))
+    }
 }
@@ -0,0 +1,98 @@
+//! Events: server-side conditions which the client can wait for.
+//!
+//! An event is built from an [`Expression`] evaluated by the server. It is backed by a `bool`
+//! stream which becomes `true` when the expression holds.
+use crate::client::CallHandle;
+use crate::codec;
+use crate::krpc;
+use crate::stream::StreamHandle;
+
+/// An expression object living on the server, returned by the procedures of the
+/// `KRPC.Expression` class.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Expression(pub(crate) u64);
+
+impl Expression {
+    #[doc(hidden)]
+    /// Creates a new Expression from its object id. The function is public so that the generated
+    /// code from krpc-mars-terraformer can use it but it is hidden from user docs.
+    pub fn new(id: u64) -> Self {
+        Expression(id)
+    }
+
+    /// Creates an RPC request that will add an event triggered by this expression. The
+    /// expression must evaluate to a `bool`.
+    pub fn to_event(&self) -> CallHandle<EventHandle> {
+        mk_event(self)
+    }
+}
+
+impl codec::RPCExtractable for Expression {
+    fn extract_value(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Self, protobuf::ProtobufError> {
+        Ok(Expression(input.read_uint64()?))
+    }
+}
+
+impl codec::Nullable for Expression {}
+
+impl codec::RPCEncodable for Expression {
+    fn encode(
+        &self,
+        output: &mut protobuf::CodedOutputStream,
+    ) -> Result<(), protobuf::ProtobufError> {
+        output.write_uint64_no_tag(self.0)
+    }
+}
+
+/// A handle to an event. The event fires when the value of its stream becomes `true`.
+///
+/// Events are created paused: call [`EventHandle::start`] before waiting for one.
+///
+/// # Example:
+/// ```rust,ignore
+///let event = client.mk_call(&expression.to_event())?;
+///client.mk_call(&event.start())?;
+///
+///stream_client.wait_for_event(&event, Some(Duration::from_secs(60)))?;
+///client.mk_call(&event.remove())?;
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct EventHandle {
+    stream: StreamHandle<bool>,
+}
+
+impl EventHandle {
+    /// Returns the stream backing this event.
+    pub fn stream(&self) -> StreamHandle<bool> {
+        self.stream
+    }
+
+    /// Creates an RPC request that will start sending updates of this event.
+    pub fn start(&self) -> CallHandle<()> {
+        self.stream.start()
+    }
+
+    /// Creates an RPC request that will remove this event.
+    pub fn remove(&self) -> CallHandle<()> {
+        self.stream.remove()
+    }
+}
+
+impl codec::RPCExtractable for EventHandle {
+    fn extract_value(
+        input: &mut protobuf::CodedInputStream,
+    ) -> Result<Self, protobuf::ProtobufError> {
+        let event = krpc::Event::extract_value(input)?;
+        Ok(EventHandle {
+            stream: StreamHandle::new(event.get_stream().id),
+        })
+    }
+}
+
+/// Creates an event request from an expression. For less verbosity, you can use
+/// [`Expression::to_event`] instead.
+pub fn mk_event(expression: &Expression) -> CallHandle<EventHandle> {
+    crate::core::add_event(expression)
+}
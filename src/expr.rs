@@ -0,0 +1,323 @@
+//! A typed builder for `KRPC.Expression` objects.
+//!
+//! An [`Expr`] is a tree built on the client side. Its type parameter is the type of the value
+//! the expression evaluates to, so that only values of the same type can be compared or combined:
+//!
+//! ```rust,ignore
+//!use krpc_mars::expr::Expr;
+//!
+//!let altitude = Expr::call(&flight.get_mean_altitude());
+//!let condition = altitude.greater_than(10_000.0) & !Expr::call(&vessel.get_landed());
+//!
+//!let expression = condition.build(&mut client)?;
+//!let event = client.mk_call(&expression.to_event())?;
+//! ```
+//!
+//! [`Expr::build`] creates the expression objects on the server, one request per level of the
+//! tree.
+use crate::client::{CallHandle, RPCClient, RPCRequest};
+use crate::codec;
+use crate::core::krpc_call;
+use crate::error;
+use crate::event::Expression;
+use crate::transport::Transport;
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops;
+use std::sync::Arc;
+
+use codec::RPCEncodable;
+
+/// An expression evaluating to a value of type `T`.
+///
+/// Expressions of different types can't be mixed:
+///
+/// ```compile_fail
+///use krpc_mars::Expr;
+///
+///let condition = Expr::constant(1.0).equal(true);
+/// ```
+#[derive(Debug)]
+pub struct Expr<T> {
+    node: Arc<Node>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> Clone for Expr<T> {
+    fn clone(&self) -> Self {
+        Expr {
+            node: Arc::clone(&self.node),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// A call to one of the static procedures of the `KRPC.Expression` class.
+#[derive(Debug)]
+struct Node {
+    procedure: &'static str,
+    args: Vec<Arg>,
+}
+
+#[derive(Debug)]
+enum Arg {
+    /// An encoded value.
+    Value(Vec<u8>),
+    /// A subexpression, passed as the id of its expression object.
+    Expr(Arc<Node>),
+}
+
+/// Types which can be used as constants in an expression.
+pub trait Constant: codec::RPCEncodable {
+    #[doc(hidden)]
+    const PROCEDURE: &'static str;
+}
+
+impl Constant for f64 {
+    const PROCEDURE: &'static str = "ConstantDouble";
+}
+
+impl Constant for f32 {
+    const PROCEDURE: &'static str = "ConstantFloat";
+}
+
+impl Constant for i32 {
+    const PROCEDURE: &'static str = "ConstantInt";
+}
+
+impl Constant for bool {
+    const PROCEDURE: &'static str = "ConstantBool";
+}
+
+impl Constant for String {
+    const PROCEDURE: &'static str = "ConstantString";
+}
+
+/// Types supporting arithmetic in an expression.
+pub trait Numeric: Constant {}
+
+impl Numeric for f64 {}
+impl Numeric for f32 {}
+impl Numeric for i32 {}
+
+impl<T> Expr<T> {
+    fn node(procedure: &'static str, args: Vec<Arg>) -> Self {
+        Expr {
+            node: Arc::new(Node { procedure, args }),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn binary<U>(procedure: &'static str, lhs: Expr<T>, rhs: Expr<T>) -> Expr<U> {
+        Expr::node(procedure, vec![Arg::Expr(lhs.node), Arg::Expr(rhs.node)])
+    }
+
+    /// An expression evaluating to the result of a procedure call. The call is made by the server
+    /// each time the expression is evaluated.
+    pub fn call(handle: &CallHandle<T>) -> Self
+    where
+        T: codec::RPCExtractable,
+    {
+        let call = handle.get_call().encode_to_bytes().unwrap();
+        Expr::node("Call", vec![Arg::Value(call)])
+    }
+
+    /// `self == rhs`
+    pub fn equal(self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+        Expr::binary("Equal", self, rhs.into())
+    }
+
+    /// `self != rhs`
+    pub fn not_equal(self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+        Expr::binary("NotEqual", self, rhs.into())
+    }
+
+    /// `self > rhs`
+    pub fn greater_than(self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+        Expr::binary("GreaterThan", self, rhs.into())
+    }
+
+    /// `self >= rhs`
+    pub fn greater_than_or_equal(self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+        Expr::binary("GreaterThanOrEqual", self, rhs.into())
+    }
+
+    /// `self < rhs`
+    pub fn less_than(self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+        Expr::binary("LessThan", self, rhs.into())
+    }
+
+    /// `self <= rhs`
+    pub fn less_than_or_equal(self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+        Expr::binary("LessThanOrEqual", self, rhs.into())
+    }
+
+    /// Creates the expression objects on the server and returns the root one. Subexpressions of
+    /// the same depth are created in a single request, and a subexpression shared by several
+    /// nodes (e.g. a cloned `Expr`) is only created once.
+    pub fn build<C: Transport>(
+        &self,
+        client: &mut RPCClient<C>,
+    ) -> Result<Expression, error::RPCError> {
+        let mut nodes = Vec::new();
+        flatten(&self.node, &mut nodes, &mut HashMap::new());
+
+        let mut ids: Vec<Option<Expression>> = vec![None; nodes.len()];
+        let max_height = nodes.iter().map(|n| n.height).max().unwrap_or(0);
+        for height in 0..=max_height {
+            let level: Vec<usize> = (0..nodes.len())
+                .filter(|&i| nodes[i].height == height)
+                .collect();
+
+            let mut request = RPCRequest::default();
+            for &i in &level {
+                let mut children = nodes[i].children.iter();
+                let args = nodes[i]
+                    .node
+                    .args
+                    .iter()
+                    .map(|arg| match arg {
+                        Arg::Value(value) => Ok(value.clone()),
+                        Arg::Expr(_) => {
+                            let id = children.next().and_then(|&child| ids[child]);
+                            Ok(id.ok_or_else(missing_id)?.encode_to_bytes()?)
+                        }
+                    })
+                    .collect::<Result<_, error::RPCError>>()?;
+                let procedure = format!("Expression_static_{}", nodes[i].node.procedure);
+                request.calls.push(krpc_call(&procedure, args));
+            }
+
+            let response = client.submit_request(request)?;
+            if response.results.len() != level.len() {
+                return Err(codec::DecodeError::new(
+                    format!("{} results", level.len()),
+                    format!("{} results", response.results.len()),
+                )
+                .into());
+            }
+            for (&i, result) in level.iter().zip(response.results.iter()) {
+                ids[i] = Some(codec::extract_result(result)?);
+            }
+        }
+
+        Ok(ids.pop().flatten().ok_or_else(missing_id)?)
+    }
+}
+
+/// The id of a subexpression which should have been created before.
+fn missing_id() -> codec::DecodeError {
+    codec::DecodeError::new(String::from("an expression id"), String::from("none"))
+}
+
+/// A node of the tree along with the indices of its subexpressions.
+struct Flat<'a> {
+    node: &'a Node,
+    children: Vec<usize>,
+    /// Distance to the farthest leaf.
+    height: usize,
+}
+
+/// Lists the nodes of the tree in post-order, so that the root comes last. Nodes shared by
+/// several parents are listed once: `seen` maps them to their index.
+fn flatten<'a>(
+    node: &'a Node,
+    out: &mut Vec<Flat<'a>>,
+    seen: &mut HashMap<*const Node, usize>,
+) -> usize {
+    if let Some(&index) = seen.get(&(node as *const Node)) {
+        return index;
+    }
+    let children: Vec<usize> = node
+        .args
+        .iter()
+        .filter_map(|arg| match arg {
+            Arg::Expr(child) => Some(flatten(child, out, seen)),
+            Arg::Value(_) => None,
+        })
+        .collect();
+    let height = children
+        .iter()
+        .map(|&c| out[c].height + 1)
+        .max()
+        .unwrap_or(0);
+    out.push(Flat {
+        node,
+        children,
+        height,
+    });
+    seen.insert(node, out.len() - 1);
+    out.len() - 1
+}
+
+impl<T: Constant> Expr<T> {
+    /// An expression evaluating to a constant.
+    pub fn constant(value: T) -> Self {
+        Expr::node(
+            T::PROCEDURE,
+            vec![Arg::Value(value.encode_to_bytes().unwrap())],
+        )
+    }
+}
+
+impl<T: Constant> From<T> for Expr<T> {
+    fn from(value: T) -> Self {
+        Expr::constant(value)
+    }
+}
+
+impl From<&str> for Expr<String> {
+    fn from(value: &str) -> Self {
+        Expr::constant(value.to_string())
+    }
+}
+
+impl<T: Numeric> Expr<T> {
+    /// `self` raised to the power `rhs`.
+    pub fn pow(self, rhs: impl Into<Expr<T>>) -> Self {
+        Expr::binary("Power", self, rhs.into())
+    }
+}
+
+macro_rules! arithmetic_op {
+    ($trait:ident, $method:ident, $procedure:expr) => {
+        impl<T: Numeric, R: Into<Expr<T>>> ops::$trait<R> for Expr<T> {
+            type Output = Expr<T>;
+
+            fn $method(self, rhs: R) -> Expr<T> {
+                Expr::binary($procedure, self, rhs.into())
+            }
+        }
+    };
+}
+
+arithmetic_op!(Add, add, "Add");
+arithmetic_op!(Sub, sub, "Subtract");
+arithmetic_op!(Mul, mul, "Multiply");
+arithmetic_op!(Div, div, "Divide");
+arithmetic_op!(Rem, rem, "Modulo");
+
+macro_rules! logical_op {
+    ($trait:ident, $method:ident, $procedure:expr) => {
+        impl<R: Into<Expr<bool>>> ops::$trait<R> for Expr<bool> {
+            type Output = Expr<bool>;
+
+            fn $method(self, rhs: R) -> Expr<bool> {
+                Expr::binary($procedure, self, rhs.into())
+            }
+        }
+    };
+}
+
+logical_op!(BitAnd, bitand, "And");
+logical_op!(BitOr, bitor, "Or");
+logical_op!(BitXor, bitxor, "ExclusiveOr");
+
+impl ops::Not for Expr<bool> {
+    type Output = Expr<bool>;
+
+    fn not(self) -> Expr<bool> {
+        Expr::node("Not", vec![Arg::Expr(self.node)])
+    }
+}
@@ -7,14 +7,63 @@ pub mod client;
 pub use client::RPCClient;
 pub use client::RPCRequest;
 
+pub mod builder;
+pub use builder::ClientBuilder;
+
 pub mod stream;
 pub use stream::StreamClient;
 pub use stream::StreamUpdate;
 
+pub mod core;
+
+pub mod event;
+pub use event::EventHandle;
+
+pub mod expr;
+pub use expr::Expr;
+
+pub mod cache;
+pub use cache::StreamCache;
+
+pub mod dispatch;
+pub use dispatch::StreamDispatcher;
+
+mod worker;
+
+pub mod connection;
+pub use connection::Connection;
+
+pub mod reconnect;
+pub use reconnect::ReconnectingClient;
+
+mod dynamic;
+
+pub mod transport;
+pub use transport::Transport;
+
+pub mod serial;
+pub use serial::SerialClient;
+
+#[cfg(feature = "async")]
+pub mod async_client;
+#[cfg(feature = "async")]
+pub use async_client::AsyncRPCClient;
+
+#[cfg(feature = "async")]
+pub mod async_stream;
+#[cfg(feature = "async")]
+pub use async_stream::AsyncStreamClient;
+
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "websocket")]
+pub use websocket::{WebSocketRPCClient, WebSocketStreamClient};
+
 pub mod error;
 
 // Re-exported for the generated code
 pub mod codec;
+#[allow(warnings, clippy::all)]
 pub mod krpc;
 pub use protobuf;
 
@@ -63,3 +112,53 @@ macro_rules! batch_call_unwrap {
         $crate::batch_call_common!(|result: ::std::result::Result<_, _>| { result.unwrap() }, $client, ( $( $call ),+ ))
     }};
 }
+
+#[doc(hidden)]
+#[cfg(feature = "async")]
+#[macro_export]
+macro_rules! async_batch_call_common {
+    ($process_result:expr, $client:expr, ( $( $call:expr ),+ )) => {async {
+        let mut request = $crate::client::RPCRequest::default();
+        $( request.add_call($call); )+
+        match $client.submit_request(request).await {
+            Err(e) => {
+                Err(e)
+            }
+            Ok(ref response) => {
+                let mut _i = 0;
+                Ok(( $({
+                        let result = $call.get_result(response, _i); _i += 1;
+                        $process_result(result)
+                },)+ ))
+            }
+        }
+    }};
+}
+
+/// Asynchronous version of [`batch_call!`] for the [`AsyncRPCClient`]. The macro evaluates to a
+/// future which resolves to a tuple of `Result`s, one for each call.
+///
+/// # Example:
+/// ```rust,ignore
+///let mut client = krpc_mars::AsyncRPCClient::connect("Example", "127.0.0.1:50000").await?;
+///let (vessel, time) = async_batch_call!(&mut client, (
+///    &space_center::get_active_vessel(),
+///    &space_center::get_ut(),
+///)).await?;
+/// ```
+#[cfg(feature = "async")]
+#[macro_export]
+macro_rules! async_batch_call {
+    ($client:expr, ( $( $call:expr ),+ $(,)? )) => {
+        $crate::async_batch_call_common!(|result| { result }, $client, ( $( $call ),+ ))
+    };
+}
+
+/// Does the same as [`async_batch_call!`] but unwraps all values automatically.
+#[cfg(feature = "async")]
+#[macro_export]
+macro_rules! async_batch_call_unwrap {
+    ($client:expr, ( $( $call:expr ),+ $(,)? )) => {{
+        $crate::async_batch_call_common!(|result: ::std::result::Result<_, _>| { result.unwrap() }, $client, ( $( $call ),+ ))
+    }};
+}
@@ -0,0 +1,417 @@
+//! A client which survives the loss of its connection to the server.
+//!
+//! When KSP reloads a scene or the kRPC server restarts, both sockets die and every stream created
+//! by the client is gone. [`ReconnectingClient`] reconnects with some backoff, creates the streams
+//! again and keeps handing out the ids the caller already knows.
+use crate::builder::ClientBuilder;
+use crate::client::{CallHandle, RPCClient, RPCRequest, RPCResponse};
+use crate::codec;
+use crate::error;
+use crate::krpc;
+use crate::stream::{
+    argument, stream_change, stream_id_argument, StreamChange, StreamClient, StreamHandle,
+    StreamID, StreamUpdate,
+};
+use crate::transport::{Framed, Transport};
+
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use protobuf::Message;
+
+/// Procedures of the KRPC service whose first argument is a stream id.
+const STREAM_ID_PROCEDURES: &[&str] = &["RemoveStream", "StartStream", "SetStreamRate"];
+
+/// How long to wait between reconnection attempts. The delay doubles after each failed attempt.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// Delay before the first attempt.
+    pub initial: Duration,
+    /// Upper bound of the delay.
+    pub max: Duration,
+    /// Gives up after this many attempts. Retries forever if `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+/// Opens the connections of a [`ReconnectingClient`]. [`ClientBuilder`] connects over TCP; other
+/// implementations can reach the server through any [`Transport`].
+pub trait Connector {
+    type Transport: Transport + std::fmt::Debug;
+
+    /// Connects to the RPC server.
+    fn connect(&self) -> Result<RPCClient<Self::Transport>, error::ConnectionError>;
+
+    /// Connects to the Stream server associated with the given client.
+    fn connect_stream(
+        &self,
+        client: &RPCClient<Self::Transport>,
+    ) -> Result<StreamClient<Self::Transport>, error::ConnectionError>;
+}
+
+impl Connector for ClientBuilder {
+    type Transport = Framed<TcpStream>;
+
+    fn connect(&self) -> Result<RPCClient, error::ConnectionError> {
+        ClientBuilder::connect(self)
+    }
+
+    fn connect_stream(&self, client: &RPCClient) -> Result<StreamClient, error::ConnectionError> {
+        ClientBuilder::connect_stream(self, client)
+    }
+}
+
+/// Whether the error means that the connection is lost, as opposed to a failed call or a value
+/// which could not be decoded.
+fn is_connection_lost(e: &error::RPCError) -> bool {
+    match e {
+        error::RPCError::IOErr(_) => true,
+        error::RPCError::ProtobufErr(protobuf::ProtobufError::IoError(_)) => true,
+        #[cfg(feature = "websocket")]
+        error::RPCError::WebSocketErr(_) => true,
+        _ => false,
+    }
+}
+
+/// A stream created through the [`ReconnectingClient`].
+#[derive(Debug)]
+struct RegisteredStream {
+    /// The `AddStream` call which created the stream.
+    add_stream: krpc::ProcedureCall,
+    /// Whether the stream sends updates, i.e. it wasn't created paused or it was started since.
+    running: bool,
+    /// The rate set with `SetStreamRate`, if any.
+    rate: Option<f32>,
+    /// Id of the stream on the current connection, if it could be created again.
+    server_id: Option<StreamID>,
+}
+
+/// An [`RPCClient`] and [`StreamClient`] pair which reconnects automatically.
+///
+/// The client remembers every stream created through it (with [`CallHandle::to_stream`] or
+/// [`mk_stream`](crate::stream::mk_stream)). After a reconnection, it adds them again and maps the
+/// new stream ids to the old ones, so the [`StreamHandle`]s held by the caller stay valid. A stream
+/// which can't be created again (e.g. its vessel is gone) gets no updates, and starting it or
+/// setting its rate fails with [`RPCError::StreamNotRestored`](error::RPCError::StreamNotRestored)
+/// until a later reconnection restores it. Removing it is done without the server.
+///
+/// A request which hits a dead connection still fails: the client reconnects before returning the
+/// error, but it doesn't send the request again since it may already have been executed. Waiting
+/// for stream updates, on the other hand, goes on transparently. Other errors, such as timeouts or
+/// values which could not be decoded, are returned without reconnecting.
+///
+/// The connections are opened by a [`Connector`], the [`ClientBuilder`] passed to
+/// [`ReconnectingClient::connect`] by default.
+#[derive(Debug)]
+pub struct ReconnectingClient<C: Connector = ClientBuilder> {
+    connector: C,
+    backoff: Backoff,
+    client: RPCClient<C::Transport>,
+    stream_client: StreamClient<C::Transport>,
+    /// Streams keyed by the id known to the caller.
+    streams: HashMap<StreamID, RegisteredStream>,
+    /// Maps the ids used by the server on the current connection to the ids known to the caller.
+    caller_ids: HashMap<StreamID, StreamID>,
+}
+
+impl<C: Connector> ReconnectingClient<C> {
+    /// Connects to the RPC and Stream servers, e.g. the ones configured in a [`ClientBuilder`].
+    pub fn connect(connector: C) -> Result<Self, error::ConnectionError> {
+        let client = connector.connect()?;
+        let stream_client = connector.connect_stream(&client)?;
+        Ok(ReconnectingClient {
+            connector,
+            backoff: Backoff::default(),
+            client,
+            stream_client,
+            streams: HashMap::new(),
+            caller_ids: HashMap::new(),
+        })
+    }
+
+    /// Sets the delays between reconnection attempts.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sends a single RPC request to the server.
+    pub fn mk_call<T: codec::RPCExtractable>(
+        &mut self,
+        call: &CallHandle<T>,
+    ) -> Result<T, error::RPCError> {
+        let (result,) = crate::batch_call!(self, (call))?;
+        result
+    }
+
+    /// Sends an [`RPCRequest`] to the server. Streams added by the request are remembered so that
+    /// they can be restored after a reconnection.
+    pub fn submit_request(
+        &mut self,
+        mut request: RPCRequest,
+    ) -> Result<RPCResponse, error::RPCError> {
+        // Calls on streams which could not be restored never reach the server, where their id may
+        // belong to another stream. Removing them only needs to forget them.
+        let mut removed = Vec::new();
+        for (position, call) in request.calls.iter().enumerate() {
+            if let Some(caller_id) = self.unrestored_stream(call) {
+                if call.get_procedure() != "RemoveStream" {
+                    return Err(error::RPCError::StreamNotRestored(caller_id));
+                }
+                removed.push((position, caller_id));
+            }
+        }
+        for (position, caller_id) in removed.iter().rev() {
+            self.streams.remove(caller_id);
+            request.calls.remove(*position);
+        }
+
+        for call in request.calls.iter_mut() {
+            self.to_server_ids(call);
+        }
+
+        let mut response = if request.calls.is_empty() {
+            RPCResponse {
+                results: Default::default(),
+                limits: self.client.limits,
+            }
+        } else {
+            self.send(&request)?
+        };
+
+        for (call, result) in request.calls.iter().zip(response.results.iter_mut()) {
+            match stream_change(call, result) {
+                Some(StreamChange::Added(server_id)) => self.register(call, server_id, result)?,
+                Some(StreamChange::Removed(server_id)) => self.unregister(server_id),
+                Some(StreamChange::Started(server_id)) => self.record_start(server_id),
+                Some(StreamChange::RateSet(server_id, rate)) => self.record_rate(server_id, rate),
+                None => {}
+            }
+        }
+
+        for (position, _) in removed {
+            response
+                .results
+                .insert(position, krpc::ProcedureResult::new());
+        }
+        Ok(response)
+    }
+
+    /// Sends a request. If the connection is lost, reconnects before returning the error.
+    fn send(&mut self, request: &RPCRequest) -> Result<RPCResponse, error::RPCError> {
+        match self.client.submit_request(request.clone()) {
+            Err(e) if is_connection_lost(&e) => {
+                self.reconnect()?;
+                Err(e)
+            }
+            result => result,
+        }
+    }
+
+    /// Waits for the next stream update. If the connection is lost, reconnects and keeps waiting.
+    pub fn recv_update(&mut self) -> Result<StreamUpdate, error::RPCError> {
+        loop {
+            match self.stream_client.recv_update() {
+                Ok(mut update) => {
+                    update.updates = update
+                        .updates
+                        .into_iter()
+                        .filter_map(|(id, result)| Some((*self.caller_ids.get(&id)?, result)))
+                        .collect();
+                    return Ok(update);
+                }
+                Err(e) if is_connection_lost(&e) => self.reconnect()?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reconnects to the server and adds all known streams again.
+    pub fn reconnect(&mut self) -> Result<(), error::RPCError> {
+        let mut delay = self.backoff.initial;
+        let mut attempts = 0;
+        loop {
+            std::thread::sleep(delay);
+            attempts += 1;
+            match self.try_reconnect() {
+                Ok(()) => return Ok(()),
+                Err(e) if matches!(self.backoff.max_attempts, Some(max) if attempts >= max) => {
+                    return Err(error::RPCError::ReconnectFailed(e));
+                }
+                Err(_) => delay = std::cmp::min(delay.saturating_mul(2), self.backoff.max),
+            }
+        }
+    }
+
+    fn try_reconnect(&mut self) -> Result<(), error::ConnectionError> {
+        let mut client = self.connector.connect()?;
+        let stream_client = self.connector.connect_stream(&client)?;
+
+        let mut caller_ids = HashMap::new();
+        if !self.streams.is_empty() {
+            let ids: Vec<StreamID> = self.streams.keys().copied().collect();
+            let mut request = RPCRequest::default();
+            for id in &ids {
+                let stream = &self.streams[id];
+                // A stream with a rate is added paused and started once its rate is set, so that
+                // it doesn't send updates at the default rate meanwhile.
+                let start = stream.running && stream.rate.is_none();
+                request.calls.push(with_start(&stream.add_stream, start));
+            }
+
+            let response = client.submit_request(request)?;
+
+            let mut rates = RPCRequest::default();
+            for (id, result) in ids.iter().zip(response.results.iter()) {
+                // Streams which can't be created anymore (e.g. their vessel is gone) are kept, in
+                // case they come back after the next reconnection.
+                let server_id = codec::extract_result::<StreamHandle<()>>(result)
+                    .ok()
+                    .map(|handle| handle.stream_id);
+                let stream = self.streams.get_mut(id).unwrap();
+                if let Some(server_id) = server_id {
+                    caller_ids.insert(server_id, *id);
+                    if let Some(rate) = stream.rate {
+                        let handle = StreamHandle::<()>::new(server_id);
+                        rates.add_call(&handle.set_rate(rate));
+                        if stream.running {
+                            rates.add_call(&handle.start());
+                        }
+                    }
+                }
+                stream.server_id = server_id;
+            }
+
+            if !rates.calls.is_empty() {
+                client.submit_request(rates)?;
+            }
+        }
+
+        self.client = client;
+        self.stream_client = stream_client;
+        self.caller_ids = caller_ids;
+        Ok(())
+    }
+
+    /// Records the stream created by an `AddStream` call, and rewrites the result so that it
+    /// carries the id known to the caller.
+    fn register(
+        &mut self,
+        call: &krpc::ProcedureCall,
+        server_id: StreamID,
+        result: &mut krpc::ProcedureResult,
+    ) -> Result<(), error::RPCError> {
+        let caller_id = match self.caller_ids.get(&server_id) {
+            // The server returns the same stream for identical calls.
+            Some(caller_id) => *caller_id,
+            None => {
+                let mut caller_id = server_id;
+                while self.streams.contains_key(&caller_id) {
+                    caller_id += 1;
+                }
+                self.streams.insert(
+                    caller_id,
+                    RegisteredStream {
+                        add_stream: call.clone(),
+                        running: argument(call, 1).unwrap_or(true),
+                        rate: None,
+                        server_id: Some(server_id),
+                    },
+                );
+                self.caller_ids.insert(server_id, caller_id);
+                caller_id
+            }
+        };
+
+        if caller_id != server_id {
+            let mut stream = krpc::Stream::new();
+            stream.set_id(caller_id);
+            result.set_value(stream.write_to_bytes()?);
+        }
+        Ok(())
+    }
+
+    /// Forgets a stream removed by a `RemoveStream` call. The call has already been rewritten to
+    /// use the server's id.
+    fn unregister(&mut self, server_id: StreamID) {
+        if let Some(caller_id) = self.caller_ids.remove(&server_id) {
+            self.streams.remove(&caller_id);
+        }
+    }
+
+    /// Remembers that a stream created paused was started, so that it is started again after a
+    /// reconnection.
+    fn record_start(&mut self, server_id: StreamID) {
+        if let Some(stream) = self.registered_mut(server_id) {
+            stream.running = true;
+        }
+    }
+
+    /// Remembers the rate of a stream, so that it can be set again after a reconnection.
+    fn record_rate(&mut self, server_id: StreamID, rate: f32) {
+        if let Some(stream) = self.registered_mut(server_id) {
+            stream.rate = Some(rate);
+        }
+    }
+
+    /// Returns the stream with the given id on the server.
+    fn registered_mut(&mut self, server_id: StreamID) -> Option<&mut RegisteredStream> {
+        let caller_id = self.caller_ids.get(&server_id)?;
+        self.streams.get_mut(caller_id)
+    }
+
+    /// Returns the id of the stream targeted by a call taking a stream id, if that stream was
+    /// created through this client but not restored after the last reconnection.
+    fn unrestored_stream(&self, call: &krpc::ProcedureCall) -> Option<StreamID> {
+        if call.get_service() != "KRPC" || !STREAM_ID_PROCEDURES.contains(&call.get_procedure()) {
+            return None;
+        }
+        let caller_id = stream_id_argument(call)?;
+        match self.streams.get(&caller_id)?.server_id {
+            Some(_) => None,
+            None => Some(caller_id),
+        }
+    }
+
+    /// Replaces the stream id known to the caller with the one used by the server, in calls
+    /// taking a stream id.
+    fn to_server_ids(&self, call: &mut krpc::ProcedureCall) {
+        if call.get_service() != "KRPC" || !STREAM_ID_PROCEDURES.contains(&call.get_procedure()) {
+            return;
+        }
+        let server_id = stream_id_argument(call)
+            .and_then(|id| self.streams.get(&id))
+            .and_then(|stream| stream.server_id);
+        if let Some(server_id) = server_id {
+            use codec::RPCEncodable;
+            if let Some(arg) = call.mut_arguments().iter_mut().find(|a| a.position == 0) {
+                arg.set_value(server_id.encode_to_bytes().unwrap());
+            }
+        }
+    }
+}
+
+/// Returns a copy of an `AddStream` call which creates the stream running or paused. Like
+/// [`mk_stream`](crate::stream::mk_stream), the `start` argument is only sent when `false`.
+fn with_start(add_stream: &krpc::ProcedureCall, start: bool) -> krpc::ProcedureCall {
+    let mut call = add_stream.clone();
+    call.mut_arguments().retain(|a| a.position == 0);
+    if !start {
+        use codec::RPCEncodable;
+        let mut arg = krpc::Argument::new();
+        arg.set_position(1);
+        arg.set_value(false.encode_to_bytes().unwrap());
+        call.mut_arguments().push(arg);
+    }
+    call
+}
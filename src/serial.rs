@@ -0,0 +1,104 @@
+//! Client for the kRPC SerialIO server.
+//!
+//! Over a serial line, RPCs and stream updates share a single connection. Requests are wrapped in
+//! `krpc::MultiplexedRequest` messages and the server answers with `krpc::MultiplexedResponse`
+//! messages, which carry either the response to a request or a stream update.
+use crate::client::{CallHandle, RPCRequest, RPCResponse};
+use crate::codec;
+use crate::error;
+use crate::krpc;
+use crate::stream::StreamUpdate;
+use crate::transport::{Framed, Transport};
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+/// A client to the SerialIO server. It works over any byte stream, such as a tty opened with the
+/// `serialport` crate.
+///
+/// Since RPC responses and stream updates arrive on the same line, updates received while waiting
+/// for a response are queued and returned by later calls to [`SerialClient::recv_update`].
+#[derive(Debug)]
+pub struct SerialClient<S> {
+    port: Framed<S>,
+    pending_updates: VecDeque<StreamUpdate>,
+    limits: codec::Limits,
+}
+
+impl<S: Read + Write> SerialClient<S> {
+    /// Connects to the SerialIO server on the other end of `port`. The client will show up in the
+    /// KRPC UI with the given client name.
+    pub fn connect(client_name: &str, port: S) -> Result<Self, error::ConnectionError> {
+        let mut port = Framed::new(port);
+
+        let mut conn_req = krpc::ConnectionRequest::new();
+        conn_req.set_field_type(krpc::ConnectionRequest_Type::RPC);
+        conn_req.set_client_name(client_name.to_string());
+
+        let mut request = krpc::MultiplexedRequest::new();
+        request.set_connection_request(conn_req);
+
+        port.send_message(&request)?;
+
+        let response = port.recv_message::<krpc::ConnectionResponse>()?;
+        crate::client::accept_connection(response)?;
+
+        Ok(SerialClient {
+            port,
+            pending_updates: VecDeque::new(),
+            limits: codec::Limits::default(),
+        })
+    }
+
+    /// Sets the limits on the messages accepted from the server.
+    pub fn set_limits(&mut self, limits: codec::Limits) {
+        self.port.set_limits(&limits);
+        self.limits = limits;
+    }
+
+    /// Sends a single RPC request to the server.
+    pub fn mk_call<T: codec::RPCExtractable>(
+        &mut self,
+        call: &CallHandle<T>,
+    ) -> Result<T, error::RPCError> {
+        let (result,) = crate::batch_call!(self, (call))?;
+        result
+    }
+
+    /// Sends an [`RPCRequest`] to the server. A single RPCRequest may contain multiple RPC calls.
+    /// It is recommended to use the [`batch_call!`](crate::batch_call) or
+    /// [`batch_call_unwrap!`](crate::batch_call_unwrap) for one-off requests.
+    pub fn submit_request(&mut self, request: RPCRequest) -> Result<RPCResponse, error::RPCError> {
+        let mut multiplexed = krpc::MultiplexedRequest::new();
+        multiplexed.set_request(request.build());
+
+        self.port.send_message(&multiplexed)?;
+
+        loop {
+            let mut message = self.port.recv_message::<krpc::MultiplexedResponse>()?;
+            if message.has_stream_update() {
+                let update = StreamUpdate::from_message(message.take_stream_update(), self.limits);
+                self.pending_updates.push_back(update);
+            }
+            if message.has_response() {
+                return RPCResponse::from_message(message.take_response(), self.limits);
+            }
+        }
+    }
+
+    /// Returns the next stream update, waiting for one if none were received while doing RPCs.
+    pub fn recv_update(&mut self) -> Result<StreamUpdate, error::RPCError> {
+        if let Some(update) = self.pending_updates.pop_front() {
+            return Ok(update);
+        }
+
+        loop {
+            let mut message = self.port.recv_message::<krpc::MultiplexedResponse>()?;
+            // The server only sends responses to our requests, and we are not waiting for one.
+            if message.has_stream_update() {
+                let update = message.take_stream_update();
+                return Ok(StreamUpdate::from_message(update, self.limits));
+            }
+        }
+    }
+}
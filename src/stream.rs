@@ -4,22 +4,26 @@ use crate::error;
 use crate::krpc;
 
 use crate::client::CallHandle;
+use crate::client::RPCClient;
+use crate::event::EventHandle;
+use crate::transport::{Framed, Transport};
 
 use std::net::TcpStream;
 use std::net::ToSocketAddrs;
 
 use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 
 use std::collections::HashMap;
 
-use protobuf::Message;
-
 pub(crate) type StreamID = u64;
 
-/// A client to the Stream server.
+/// A client to the Stream server. The type parameter is the [`Transport`] used to reach the
+/// server, plain TCP by default.
 #[derive(Debug)]
-pub struct StreamClient {
-    sock: TcpStream,
+pub struct StreamClient<T = Framed<TcpStream>> {
+    transport: T,
+    limits: codec::Limits,
 }
 
 /// A handle to a stream. The type parameter is the type of the value produced by the stream.
@@ -42,21 +46,19 @@ impl<T> StreamHandle<T> {
 
     /// Creates an RPC request that will remove this stream.
     pub fn remove(self) -> CallHandle<()> {
-        use codec::RPCEncodable;
-
-        let mut arg = krpc::Argument::new();
-        arg.set_position(0);
-        arg.set_value(self.stream_id.encode_to_bytes().unwrap());
-
-        let mut arguments = protobuf::RepeatedField::<krpc::Argument>::new();
-        arguments.push(arg);
+        crate::core::remove_stream(self)
+    }
 
-        let mut proc_call = krpc::ProcedureCall::new();
-        proc_call.set_service(String::from("KRPC"));
-        proc_call.set_procedure(String::from("RemoveStream"));
-        proc_call.set_arguments(arguments);
+    /// Creates an RPC request that will start this stream, if it was created with
+    /// [`CallHandle::to_stream_paused`]. Starting a stream which already runs does nothing.
+    pub fn start(self) -> CallHandle<()> {
+        crate::core::start_stream(self)
+    }
 
-        CallHandle::<()>::new(proc_call)
+    /// Creates an RPC request that will limit the update rate of this stream to `hz` updates per
+    /// second. A rate of 0 removes the limit.
+    pub fn set_rate(self, hz: f32) -> CallHandle<()> {
+        crate::core::set_stream_rate(self, hz)
     }
 }
 
@@ -67,71 +69,196 @@ impl<T> StreamHandle<T> {
 /// request of a stream request. Turns out this is accepted by the RPC server and the author of
 /// this library confesses he had some fun with this.
 pub fn mk_stream<T: codec::RPCExtractable>(call: &CallHandle<T>) -> CallHandle<StreamHandle<T>> {
-    let mut arg = krpc::Argument::new();
-    arg.set_position(0);
-    arg.set_value(call.get_call().write_to_bytes().unwrap());
+    crate::core::add_stream(call, true)
+}
+
+/// Creates a stream request which doesn't send updates until [`StreamHandle::start`] is called.
+/// For less verbosity, you can use the [`CallHandle::to_stream_paused`] instead.
+pub fn mk_paused_stream<T: codec::RPCExtractable>(
+    call: &CallHandle<T>,
+) -> CallHandle<StreamHandle<T>> {
+    crate::core::add_stream(call, false)
+}
 
-    let mut arguments = protobuf::RepeatedField::<krpc::Argument>::new();
-    arguments.push(arg);
+/// Returns the stream id passed as first argument of a call such as `KRPC.RemoveStream`.
+pub(crate) fn stream_id_argument(call: &krpc::ProcedureCall) -> Option<StreamID> {
+    argument(call, 0)
+}
 
-    let mut proc_call = krpc::ProcedureCall::new();
-    proc_call.set_service(String::from("KRPC"));
-    proc_call.set_procedure(String::from("AddStream"));
-    proc_call.set_arguments(arguments);
+/// Decodes the argument of a call at the given position.
+pub(crate) fn argument<T: codec::RPCExtractable>(
+    call: &krpc::ProcedureCall,
+    position: u32,
+) -> Option<T> {
+    let arg = call
+        .get_arguments()
+        .iter()
+        .find(|a| a.position == position)?;
+    let mut input = protobuf::CodedInputStream::from_bytes(arg.get_value());
+    T::extract_value(&mut input).ok()
+}
 
-    CallHandle::<StreamHandle<T>>::new(proc_call)
+/// A change made to the streams of a client by a call of the KRPC service.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum StreamChange {
+    Added(StreamID),
+    Removed(StreamID),
+    Started(StreamID),
+    RateSet(StreamID, f32),
+}
+
+/// Returns the change made to the streams by a call, given its result. Failed calls don't change
+/// anything.
+pub(crate) fn stream_change(
+    call: &krpc::ProcedureCall,
+    result: &krpc::ProcedureResult,
+) -> Option<StreamChange> {
+    if result.has_error() || call.get_service() != "KRPC" {
+        return None;
+    }
+    match call.get_procedure() {
+        "AddStream" => {
+            let handle = codec::extract_result::<StreamHandle<()>>(result).ok()?;
+            Some(StreamChange::Added(handle.stream_id))
+        }
+        "RemoveStream" => Some(StreamChange::Removed(stream_id_argument(call)?)),
+        "StartStream" => Some(StreamChange::Started(stream_id_argument(call)?)),
+        "SetStreamRate" => Some(StreamChange::RateSet(
+            stream_id_argument(call)?,
+            argument(call, 1)?,
+        )),
+        _ => None,
+    }
 }
 
 impl StreamClient {
     /// Connect to the stream server associated with the given client.
-    pub fn connect<A: ToSocketAddrs>(
-        client: &super::RPCClient,
+    pub fn connect<C, A: ToSocketAddrs>(
+        client: &RPCClient<C>,
         addr: A,
     ) -> Result<Self, error::ConnectionError> {
-        let mut sock = TcpStream::connect(addr)?;
+        let sock = TcpStream::connect(addr)?;
+        Self::with_transport(client, Framed::new(sock))
+    }
+
+    /// Waits until the stream of the event becomes `true`, for at most `timeout` if one is given.
+    /// Returns the updates of all streams received in the meantime, merged together. Fails with
+    /// [`RPCError::Timeout`](error::RPCError::Timeout) if the event didn't fire in time.
+    ///
+    /// The event must have been started with [`EventHandle::start`].
+    pub fn wait_for_event(
+        &mut self,
+        event: &EventHandle,
+        timeout: Option<Duration>,
+    ) -> Result<StreamUpdate, error::RPCError> {
+        let sock = self.transport.get_ref();
+        let _restore = RestoreTimeout {
+            timeout: sock.read_timeout()?,
+            sock: sock.try_clone()?,
+        };
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        let mut received = StreamUpdate::default();
+        loop {
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(error::RPCError::Timeout);
+                }
+                self.transport.get_ref().set_read_timeout(Some(remaining))?;
+            }
+
+            let update = self.recv_update()?;
+            let fired = update.get_result(&event.stream())?;
+            received.merge_with(update);
+            if fired == Some(true) {
+                return Ok(received);
+            }
+        }
+    }
+}
 
+/// Restores the read timeout of a socket when dropped, whichever way the function setting it
+/// returns. The socket is a clone sharing the options of the one used by the client.
+struct RestoreTimeout {
+    sock: TcpStream,
+    timeout: Option<Duration>,
+}
+
+impl Drop for RestoreTimeout {
+    fn drop(&mut self) {
+        let _ = self.sock.set_read_timeout(self.timeout);
+    }
+}
+
+impl<T: Transport> StreamClient<T> {
+    /// Connect to the stream server associated with the given client, through an already
+    /// established transport.
+    pub fn with_transport<C>(
+        client: &RPCClient<C>,
+        mut transport: T,
+    ) -> Result<Self, error::ConnectionError> {
         let mut conn_req = krpc::ConnectionRequest::new();
         conn_req.set_field_type(krpc::ConnectionRequest_Type::STREAM);
         conn_req.set_client_identifier(client.client_id.clone());
 
-        conn_req.write_length_delimited_to_writer(&mut sock)?;
+        let response = transport.handshake(&conn_req)?;
+        crate::client::accept_connection(response)?;
 
-        let mut response = codec::read_message::<krpc::ConnectionResponse>(&mut sock)?;
+        Ok(Self {
+            transport,
+            limits: codec::Limits::default(),
+        })
+    }
 
-        match response.status {
-            krpc::ConnectionResponse_Status::OK => Ok(Self { sock }),
-            s => Err(error::ConnectionError::ConnectionRefused {
-                error: response.take_message(),
-                status: s,
-            }),
-        }
+    /// Sets the limits on the updates accepted from the server.
+    pub fn set_limits(&mut self, limits: codec::Limits) {
+        self.transport.set_limits(&limits);
+        self.limits = limits;
     }
 
     pub fn recv_update(&mut self) -> Result<StreamUpdate, error::RPCError> {
-        let updates = codec::read_message::<krpc::StreamUpdate>(&mut self.sock)?;
+        let updates = self.transport.recv_message::<krpc::StreamUpdate>()?;
+        Ok(StreamUpdate::from_message(updates, self.limits))
+    }
 
-        let mut map = HashMap::new();
-        for mut result in updates.results.into_iter() {
-            map.insert(result.id, result.take_result());
-        }
+    /// Returns the transport used by this client.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
 
-        Ok(StreamUpdate { updates: map })
+    pub(crate) fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
     }
 }
 
 /// A collection of updates received from the stream server.
 #[derive(Debug, Clone, Default)]
 pub struct StreamUpdate {
-    updates: HashMap<StreamID, krpc::ProcedureResult>,
+    pub(crate) updates: HashMap<StreamID, krpc::ProcedureResult>,
+    /// Limits of the client which received the updates, checked when extracting results.
+    pub(crate) limits: codec::Limits,
 }
 
 impl StreamUpdate {
+    pub(crate) fn from_message(updates: krpc::StreamUpdate, limits: codec::Limits) -> Self {
+        let mut map = HashMap::new();
+        for mut result in updates.results.into_iter() {
+            map.insert(result.id, result.take_result());
+        }
+
+        StreamUpdate {
+            updates: map,
+            limits,
+        }
+    }
+
     pub fn get_result<T>(&self, handle: &StreamHandle<T>) -> Result<Option<T>, error::RPCError>
     where
         T: codec::RPCExtractable,
     {
         if let Some(result) = self.updates.get(&handle.stream_id) {
-            let res = codec::extract_result(&result)?;
+            let res = codec::with_limits(&self.limits, || codec::extract_result(result))?;
             Ok(Some(res))
         } else {
             Ok(None)
@@ -141,6 +268,7 @@ impl StreamUpdate {
     /// Merge two update objects. The Stream server doesn't update values that don't change, so
     /// this can be used to retain previous values of streams.
     pub fn merge_with(&mut self, other: StreamUpdate) {
-        self.updates.extend(other.updates)
+        self.updates.extend(other.updates);
+        self.limits = other.limits;
     }
 }
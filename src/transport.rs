@@ -0,0 +1,128 @@
+//! Transports carrying kRPC messages between the clients and the server.
+//!
+//! [`RPCClient`](crate::RPCClient) and [`StreamClient`](crate::StreamClient) talk to the server
+//! through a [`Transport`]. By default, they use length-delimited protobuf messages over a
+//! `TcpStream`, but any byte stream can be used by wrapping it in a [`Framed`] transport: Unix
+//! sockets, in-memory pipes, TLS streams, SSH tunnels...
+//!
+//! Transports only move encoded messages around, so the trait is object-safe and clients can use
+//! a `Box<dyn Transport>` chosen at runtime.
+use crate::codec;
+use crate::krpc;
+
+use std::io::{Read, Write};
+
+use protobuf::Message;
+
+/// Something that can carry kRPC messages to and from the server.
+pub trait Transport {
+    /// Sends an encoded message to the server.
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), protobuf::ProtobufError>;
+
+    /// Waits for the next message from the server and returns its encoded bytes.
+    fn recv_frame(&mut self) -> Result<Vec<u8>, protobuf::ProtobufError>;
+
+    /// Sends a connection request and returns the server's answer. The default implementation
+    /// sends the request as a regular message and reads the response that follows.
+    fn handshake(
+        &mut self,
+        request: &krpc::ConnectionRequest,
+    ) -> Result<krpc::ConnectionResponse, protobuf::ProtobufError> {
+        self.send_frame(&request.write_to_bytes()?)?;
+        krpc::ConnectionResponse::parse_from_bytes(&self.recv_frame()?)
+    }
+
+    /// Applies the limits of the client using the transport. Transports which read the length of
+    /// messages from the wire should reject messages larger than `limits.max_frame_size`. The
+    /// default implementation does nothing.
+    fn set_limits(&mut self, _limits: &codec::Limits) {}
+
+    /// Sends a message to the server.
+    fn send_message<M: Message>(&mut self, msg: &M) -> Result<(), protobuf::ProtobufError>
+    where
+        Self: Sized,
+    {
+        self.send_frame(&msg.write_to_bytes()?)
+    }
+
+    /// Waits for the next message from the server.
+    fn recv_message<M: Message>(&mut self) -> Result<M, protobuf::ProtobufError>
+    where
+        Self: Sized,
+    {
+        M::parse_from_bytes(&self.recv_frame()?)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), protobuf::ProtobufError> {
+        (**self).send_frame(frame)
+    }
+
+    fn recv_frame(&mut self) -> Result<Vec<u8>, protobuf::ProtobufError> {
+        (**self).recv_frame()
+    }
+
+    fn handshake(
+        &mut self,
+        request: &krpc::ConnectionRequest,
+    ) -> Result<krpc::ConnectionResponse, protobuf::ProtobufError> {
+        (**self).handshake(request)
+    }
+
+    fn set_limits(&mut self, limits: &codec::Limits) {
+        (**self).set_limits(limits)
+    }
+}
+
+/// A transport sending length-delimited protobuf messages over a byte stream. This is the
+/// protocol spoken by the kRPC TCP server.
+#[derive(Debug)]
+pub struct Framed<S> {
+    inner: S,
+    reader: codec::FramedReader,
+}
+
+impl<S> Framed<S> {
+    /// Wraps a byte stream.
+    pub fn new(inner: S) -> Self {
+        Framed {
+            inner,
+            reader: codec::FramedReader::default(),
+        }
+    }
+
+    /// Returns a reference to the underlying byte stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying byte stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Unwraps the underlying byte stream. Bytes that were received but not decoded yet are lost.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Read + Write> Transport for Framed<S> {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), protobuf::ProtobufError> {
+        let mut output = protobuf::CodedOutputStream::new(&mut self.inner);
+        output.write_raw_varint32(frame.len() as u32)?;
+        output.write_raw_bytes(frame)?;
+        output.flush()?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    fn recv_frame(&mut self) -> Result<Vec<u8>, protobuf::ProtobufError> {
+        self.reader.read_frame(&mut self.inner)
+    }
+
+    fn set_limits(&mut self, limits: &codec::Limits) {
+        self.reader.set_max_frame_size(limits.max_frame_size);
+    }
+}
@@ -0,0 +1,210 @@
+//! Transport to the kRPC WebSocket server.
+//!
+//! kRPC can serve RPCs and streams over WebSockets instead of raw TCP. Each `krpc::Request`,
+//! `krpc::Response` and `krpc::StreamUpdate` travels in a single binary frame, and the connection
+//! handshake is done through the query string of the URL rather than with a `ConnectionRequest`
+//! message. The [`WebSocket`] transport takes care of both, so the regular [`RPCClient`] and
+//! [`StreamClient`] can be used over it.
+//!
+//! This module is only available with the `websocket` feature.
+use crate::client::RPCClient;
+use crate::codec;
+use crate::error;
+use crate::krpc;
+use crate::stream::StreamClient;
+use crate::transport::Transport;
+
+use std::net::TcpStream;
+
+use base64::Engine;
+use protobuf::Message;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::stream::MaybeTlsStream;
+
+type Socket = tungstenite::WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// A client to the RPC server, over WebSockets.
+pub type WebSocketRPCClient = RPCClient<WebSocket>;
+
+/// A client to the Stream server, over WebSockets.
+pub type WebSocketStreamClient = StreamClient<WebSocket>;
+
+/// A transport to the kRPC WebSocket server. The connection is opened by the handshake of the
+/// client using the transport.
+#[derive(Debug)]
+pub struct WebSocket {
+    url: String,
+    sock: Option<Socket>,
+    config: WebSocketConfig,
+}
+
+/// Connects to the KRPC server at the given URL (e.g. `ws://127.0.0.1:50000`). The client will
+/// show up in the KRPC UI with the given client name.
+pub fn connect(client_name: &str, url: &str) -> Result<WebSocketRPCClient, error::ConnectionError> {
+    RPCClient::with_transport(client_name, WebSocket::new(url))
+}
+
+/// Connect to the stream server at the given URL (e.g. `ws://127.0.0.1:50001`), on behalf of the
+/// given client.
+pub fn connect_stream<C>(
+    client: &RPCClient<C>,
+    url: &str,
+) -> Result<WebSocketStreamClient, error::ConnectionError> {
+    StreamClient::with_transport(client, WebSocket::new(url))
+}
+
+impl WebSocket {
+    /// Creates a transport to the server at the given URL. Nothing is sent until the transport is
+    /// given to [`RPCClient::with_transport`] or [`StreamClient::with_transport`].
+    pub fn new(url: &str) -> Self {
+        let mut config = WebSocketConfig::default();
+        apply_limits(&mut config, &codec::Limits::default());
+        WebSocket {
+            url: url.trim_end_matches('/').to_string(),
+            sock: None,
+            config,
+        }
+    }
+
+    /// Returns the underlying socket, once connected.
+    pub fn get_ref(&self) -> Option<&Socket> {
+        self.sock.as_ref()
+    }
+
+    fn sock(&mut self) -> Result<&mut Socket, protobuf::ProtobufError> {
+        self.sock.as_mut().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "the WebSocket transport has not done its handshake",
+            )
+            .into()
+        })
+    }
+}
+
+impl Transport for WebSocket {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), protobuf::ProtobufError> {
+        self.sock()?
+            .send(tungstenite::Message::Binary(frame.to_vec()))
+            .map_err(into_protobuf)
+    }
+
+    /// Reads the next binary frame. Control frames are skipped.
+    fn recv_frame(&mut self) -> Result<Vec<u8>, protobuf::ProtobufError> {
+        let sock = self.sock()?;
+        loop {
+            match sock.read().map_err(into_protobuf)? {
+                tungstenite::Message::Binary(bytes) => return Ok(bytes),
+                tungstenite::Message::Close(_) => {
+                    return Err(into_protobuf(tungstenite::Error::ConnectionClosed));
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    fn handshake(
+        &mut self,
+        request: &krpc::ConnectionRequest,
+    ) -> Result<krpc::ConnectionResponse, protobuf::ProtobufError> {
+        let query = match request.get_field_type() {
+            krpc::ConnectionRequest_Type::RPC => {
+                format!("name={}", percent_encode(request.get_client_name()))
+            }
+            krpc::ConnectionRequest_Type::STREAM => {
+                let id = base64::engine::general_purpose::STANDARD
+                    .encode(request.get_client_identifier());
+                format!("client_identifier={}", percent_encode(&id))
+            }
+        };
+        let url = format!("{}/?{}", self.url, query);
+        let (sock, _) = tungstenite::client::connect_with_config(url, Some(self.config), 3)
+            .map_err(into_protobuf)?;
+        self.sock = Some(sock);
+
+        let mut response = krpc::ConnectionResponse::new();
+        if request.get_field_type() == krpc::ConnectionRequest_Type::RPC {
+            // The WebSocket handshake doesn't give us our identifier, so we ask for it.
+            let mut id_request = krpc::Request::new();
+            id_request
+                .mut_calls()
+                .push(crate::core::get_client_id().get_call().clone());
+            self.send_frame(&id_request.write_to_bytes()?)?;
+
+            let mut id_response = krpc::Response::parse_from_bytes(&self.recv_frame()?)?;
+            let error = if id_response.has_error() {
+                Some(id_response.take_error())
+            } else {
+                match id_response.mut_results().first_mut() {
+                    Some(result) if result.has_error() => Some(result.take_error()),
+                    Some(_) => None,
+                    None => {
+                        return Err(codec::DecodeError::new(
+                            "a result at index 0".into(),
+                            "0 results".into(),
+                        )
+                        .into())
+                    }
+                }
+            };
+            if let Some(mut error) = error {
+                return Err(std::io::Error::other(format!(
+                    "could not get the client identifier: {}",
+                    error.take_description()
+                ))
+                .into());
+            }
+            let mut input =
+                protobuf::CodedInputStream::from_bytes(id_response.get_results()[0].get_value());
+            let id = <crate::core::ClientID as codec::RPCExtractable>::extract_value(&mut input)?;
+            response.set_client_identifier(id.0);
+        }
+        Ok(response)
+    }
+
+    /// Limits the size of the messages accepted from the server to `limits.max_frame_size`.
+    fn set_limits(&mut self, limits: &codec::Limits) {
+        apply_limits(&mut self.config, limits);
+        if let Some(sock) = &mut self.sock {
+            sock.set_config(|config| apply_limits(config, limits));
+        }
+    }
+}
+
+fn apply_limits(config: &mut WebSocketConfig, limits: &codec::Limits) {
+    config.max_message_size = Some(limits.max_frame_size);
+    config.max_frame_size = Some(limits.max_frame_size);
+}
+
+/// Wraps a WebSocket error so that it can travel through the [`Transport`] methods. I/O errors are
+/// passed as they are and oversized messages become exceeded limits; the other errors are
+/// recovered as [`RPCError::WebSocketErr`](error::RPCError::WebSocketErr).
+fn into_protobuf(e: tungstenite::Error) -> protobuf::ProtobufError {
+    match e {
+        tungstenite::Error::Io(e) => e.into(),
+        tungstenite::Error::Capacity(tungstenite::error::CapacityError::MessageTooLong {
+            size,
+            max_size,
+        }) => codec::LimitError {
+            what: "frame size",
+            found: size,
+            max: max_size,
+        }
+        .into(),
+        e => std::io::Error::other(e).into(),
+    }
+}
+
+/// Escapes a value for use in the query string of a URL.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
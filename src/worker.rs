@@ -0,0 +1,100 @@
+//! The background thread shared by [`StreamCache`](crate::StreamCache) and
+//! [`StreamDispatcher`](crate::StreamDispatcher).
+use crate::error;
+use crate::stream::{StreamClient, StreamUpdate};
+use crate::transport::{Framed, Transport};
+
+use std::any::Any;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// A thread receiving the updates of a stream client until the connection fails or the worker is
+/// dropped. Read timeouts don't stop it.
+#[derive(Debug)]
+pub(crate) struct Worker {
+    error: Arc<Mutex<Option<error::RPCError>>>,
+    stop: Arc<AtomicBool>,
+    /// A handle on the socket of the stream client, to wake the thread up when dropped.
+    sock: Option<TcpStream>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Moves the stream client to a new thread which passes every update to `on_update`.
+    /// `on_stop` is called on that thread once it stops receiving updates, after the error which
+    /// stopped it, if any, is recorded.
+    pub(crate) fn spawn<T>(
+        mut stream_client: StreamClient<T>,
+        mut on_update: impl FnMut(StreamUpdate) + Send + 'static,
+        on_stop: impl FnOnce() + Send + 'static,
+    ) -> Self
+    where
+        T: Transport + Send + 'static,
+    {
+        let error = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let sock = tcp_stream(stream_client.transport());
+
+        let thread = {
+            let error = Arc::clone(&error);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                loop {
+                    let update = stream_client.recv_update();
+                    if stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                    match update {
+                        Ok(update) => on_update(update),
+                        // A read timeout only means that no update came in time.
+                        Err(error::RPCError::Timeout) => continue,
+                        Err(e) => {
+                            *error.lock().unwrap() = Some(e);
+                            break;
+                        }
+                    }
+                }
+                on_stop();
+            })
+        };
+
+        Worker {
+            error,
+            stop,
+            sock,
+            thread: Some(thread),
+        }
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    pub(crate) fn take_error(&self) -> Option<error::RPCError> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+/// Stops the thread. Over TCP, the socket is shut down and the thread is joined. Other transports
+/// can't be interrupted, so the thread is left to stop after the next update.
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(sock) = &self.sock {
+            if sock.shutdown(Shutdown::Both).is_ok() {
+                if let Some(thread) = self.thread.take() {
+                    let _ = thread.join();
+                }
+            }
+        }
+    }
+}
+
+/// Returns a handle on the socket of a TCP transport.
+fn tcp_stream<T: Transport + 'static>(transport: &T) -> Option<TcpStream> {
+    let transport: &dyn Any = transport;
+    let framed = transport.downcast_ref::<Framed<TcpStream>>()?;
+    framed.get_ref().try_clone().ok()
+}
@@ -0,0 +1,142 @@
+//! Runs the asynchronous clients against a local stand-in for the kRPC server.
+#![cfg(feature = "async")]
+
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::krpc;
+use krpc_mars::protobuf::{CodedInputStream, Message};
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::{AsyncRPCClient, AsyncStreamClient};
+
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+
+const CLIENT_ID: &[u8] = &[7, 8, 9];
+
+fn read<M: Message>(sock: &mut TcpStream) -> M {
+    CodedInputStream::new(sock).read_message().unwrap()
+}
+
+fn write<M: Message>(sock: &mut TcpStream, msg: &M) {
+    msg.write_length_delimited_to_writer(sock).unwrap();
+}
+
+/// Accepts a connection and answers its handshake.
+fn accept(listener: &TcpListener) -> (TcpStream, krpc::ConnectionRequest) {
+    let (mut sock, _) = listener.accept().unwrap();
+    let request = read::<krpc::ConnectionRequest>(&mut sock);
+    let mut response = krpc::ConnectionResponse::new();
+    response.set_client_identifier(CLIENT_ID.to_vec());
+    write(&mut sock, &response);
+    (sock, request)
+}
+
+#[tokio::test]
+async fn rpc_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let (mut sock, request) = accept(&listener);
+        assert_eq!(request.get_client_name(), "async test");
+
+        let request = read::<krpc::Request>(&mut sock);
+        let call = &request.get_calls()[0];
+        assert_eq!(call.get_service(), "KRPC");
+        assert_eq!(call.get_procedure(), "GetClientName");
+
+        let mut response = krpc::Response::new();
+        let mut result = krpc::ProcedureResult::new();
+        result.set_value(String::from("async test").encode_to_bytes().unwrap());
+        response.mut_results().push(result);
+        write(&mut sock, &response);
+    });
+
+    let mut client = AsyncRPCClient::connect("async test", addr).await.unwrap();
+    let name = client
+        .mk_call(&krpc_mars::core::get_client_name())
+        .await
+        .unwrap();
+    assert_eq!(name, "async test");
+    server.join().unwrap();
+}
+
+fn response(value: &str) -> krpc::Response {
+    let mut result = krpc::ProcedureResult::new();
+    result.set_value(value.to_string().encode_to_bytes().unwrap());
+    let mut response = krpc::Response::new();
+    response.mut_results().push(result);
+    response
+}
+
+#[tokio::test]
+async fn cancelled_call() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (cancelled_tx, cancelled) = std::sync::mpsc::channel();
+
+    let server = std::thread::spawn(move || {
+        let (mut sock, _) = accept(&listener);
+
+        // Only the first half of the first response arrives before the call is cancelled.
+        read::<krpc::Request>(&mut sock);
+        let bytes = response("first").write_length_delimited_to_bytes().unwrap();
+        let (head, tail) = bytes.split_at(bytes.len() / 2);
+        sock.write_all(head).unwrap();
+        cancelled.recv().unwrap();
+        sock.write_all(tail).unwrap();
+
+        read::<krpc::Request>(&mut sock);
+        write(&mut sock, &response("second"));
+    });
+
+    let mut client = AsyncRPCClient::connect("async test", addr).await.unwrap();
+    let call = krpc_mars::core::get_client_name();
+    let timeout = std::time::Duration::from_millis(100);
+    assert!(tokio::time::timeout(timeout, client.mk_call(&call))
+        .await
+        .is_err());
+    cancelled_tx.send(()).unwrap();
+
+    // The response of the cancelled call is skipped.
+    assert_eq!(client.mk_call(&call).await.unwrap(), "second");
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn stream_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let (_rpc, _) = accept(&listener);
+        let (mut sock, request) = accept(&listener);
+        assert_eq!(
+            request.get_field_type(),
+            krpc::ConnectionRequest_Type::STREAM
+        );
+        assert_eq!(request.get_client_identifier(), CLIENT_ID);
+
+        for value in [1.5f64, 2.5] {
+            let mut result = krpc::StreamResult::new();
+            result.set_id(3);
+            result
+                .mut_result()
+                .set_value(value.encode_to_bytes().unwrap());
+            let mut update = krpc::StreamUpdate::new();
+            update.mut_results().push(result);
+            write(&mut sock, &update);
+        }
+    });
+
+    let client = AsyncRPCClient::connect("async test", addr).await.unwrap();
+    let mut stream_client = AsyncStreamClient::connect(&client, addr).await.unwrap();
+    server.join().unwrap();
+
+    let handle = StreamHandle::<f64>::new(3);
+    for value in [1.5, 2.5] {
+        let update = stream_client.recv_update().await.unwrap();
+        assert_eq!(update.get_result(&handle).unwrap(), Some(value));
+    }
+    // The server closed the connection.
+    assert!(stream_client.recv_update().await.is_err());
+}
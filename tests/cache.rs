@@ -0,0 +1,128 @@
+//! Runs a StreamCache against an in-memory server and a silent TCP server.
+mod common;
+
+use common::{connection_response, eventually, stream_update, Server};
+
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::krpc;
+use krpc_mars::protobuf::{CodedInputStream, Message, ProtobufError};
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::{RPCClient, StreamCache, StreamClient, Transport};
+
+use std::io::Read;
+use std::net::TcpListener;
+
+fn spawn(server: &Server) -> StreamCache {
+    let (_, stream_client) = server.clients("cache");
+    StreamCache::spawn(stream_client)
+}
+
+#[test]
+fn keeps_latest_values() {
+    let server = Server::new(|_| unreachable!());
+    let cache = spawn(&server);
+    let a = StreamHandle::<f64>::new(1);
+    let b = StreamHandle::<f64>::new(2);
+    assert_eq!(cache.get(&a), None);
+
+    server.push_update(stream_update(1, 1.0f64.encode_to_bytes().unwrap()));
+    server.push_update(stream_update(2, 2.0f64.encode_to_bytes().unwrap()));
+    assert!(eventually(|| cache.get(&b) == Some(2.0)));
+    // The update of `b` didn't carry `a`, which keeps its value.
+    assert_eq!(cache.get(&a), Some(1.0));
+
+    server.push_update(stream_update(1, 3.0f64.encode_to_bytes().unwrap()));
+    assert!(eventually(|| cache.get(&a) == Some(3.0)));
+    assert_eq!(cache.snapshot().get_result(&b).unwrap(), Some(2.0));
+    assert!(cache.is_running());
+    assert!(cache.take_error().is_none());
+}
+
+/// A connection to a [`Server`] whose first read after the handshake times out.
+struct TimesOutOnce {
+    inner: common::MockTransport,
+    timed_out: bool,
+}
+
+impl Transport for TimesOutOnce {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), ProtobufError> {
+        self.inner.send_frame(frame)
+    }
+
+    fn recv_frame(&mut self) -> Result<Vec<u8>, ProtobufError> {
+        if !self.timed_out {
+            self.timed_out = true;
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+        }
+        self.inner.recv_frame()
+    }
+
+    fn handshake(
+        &mut self,
+        request: &krpc::ConnectionRequest,
+    ) -> Result<krpc::ConnectionResponse, ProtobufError> {
+        self.inner.handshake(request)
+    }
+}
+
+#[test]
+fn keeps_running_after_a_timeout() {
+    let server = Server::new(|_| unreachable!());
+    let client = RPCClient::with_transport("cache", server.transport()).unwrap();
+    let transport = TimesOutOnce {
+        inner: server.transport(),
+        timed_out: false,
+    };
+    let stream_client = StreamClient::with_transport(&client, transport).unwrap();
+    let cache = StreamCache::spawn(stream_client);
+    let a = StreamHandle::<f64>::new(1);
+
+    server.push_update(stream_update(1, 1.0f64.encode_to_bytes().unwrap()));
+    assert!(eventually(|| cache.get(&a) == Some(1.0)));
+    assert!(cache.is_running());
+    assert!(cache.take_error().is_none());
+}
+
+#[test]
+fn reports_connection_loss() {
+    let server = Server::new(|_| unreachable!());
+    let cache = spawn(&server);
+    let a = StreamHandle::<f64>::new(1);
+    server.push_update(stream_update(1, 1.0f64.encode_to_bytes().unwrap()));
+    assert!(eventually(|| cache.get(&a).is_some()));
+
+    server.disconnect();
+    assert!(eventually(|| !cache.is_running()));
+    assert!(cache.take_error().is_some());
+    assert!(cache.take_error().is_none());
+    // The last values are still available.
+    assert_eq!(cache.get(&a), Some(1.0));
+}
+
+#[test]
+fn drop_stops_the_thread() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let (mut sock, _) = listener.accept().unwrap();
+        CodedInputStream::new(&mut sock)
+            .read_message::<krpc::ConnectionRequest>()
+            .unwrap();
+        connection_response()
+            .write_length_delimited_to_writer(&mut sock)
+            .unwrap();
+        // Never send an update: the client closes the connection when the cache is dropped.
+        let mut rest = Vec::new();
+        sock.read_to_end(&mut rest).unwrap();
+    });
+
+    let client =
+        RPCClient::with_transport("cache", Server::new(|_| unreachable!()).transport()).unwrap();
+    let stream_client = StreamClient::connect(&client, addr).unwrap();
+    let cache = StreamCache::spawn(stream_client);
+    assert!(cache.is_running());
+
+    drop(cache);
+    server.join().unwrap();
+}
@@ -0,0 +1,348 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)] // Each test crate only uses some of them.
+
+use krpc_mars::client::CallHandle;
+use krpc_mars::codec::RPCExtractable;
+use krpc_mars::krpc;
+use krpc_mars::protobuf::{CodedInputStream, Message, ProtobufError};
+use krpc_mars::{RPCClient, StreamClient, Transport};
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// An in-memory byte stream. Reads hand out pre-recorded chunks, one per call, and writes are
+/// recorded so that the test can check what the client sent.
+pub struct ByteStream {
+    chunks: VecDeque<Vec<u8>>,
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl ByteStream {
+    pub fn new(chunks: Vec<Vec<u8>>) -> Self {
+        ByteStream {
+            chunks: chunks.into(),
+            written: Arc::default(),
+        }
+    }
+
+    /// A stream which receives the given messages, length-delimited, in a single chunk.
+    pub fn from_messages(messages: &[&dyn Message]) -> Self {
+        ByteStream::new(vec![length_delimited(messages)])
+    }
+
+    /// Returns a handle to the bytes written to the stream.
+    pub fn written(&self) -> Arc<Mutex<Vec<u8>>> {
+        Arc::clone(&self.written)
+    }
+}
+
+impl Read for ByteStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.chunks.front_mut() {
+            Some(chunk) => {
+                let n = chunk.len().min(buf.len());
+                buf[..n].copy_from_slice(&chunk[..n]);
+                chunk.drain(..n);
+                if chunk.is_empty() {
+                    self.chunks.pop_front();
+                }
+                Ok(n)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+impl Write for ByteStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.written.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Concatenates the length-delimited encodings of the messages.
+pub fn length_delimited(messages: &[&dyn Message]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for message in messages {
+        bytes.extend(message.write_length_delimited_to_bytes().unwrap());
+    }
+    bytes
+}
+
+/// Decodes all the length-delimited messages in `bytes`.
+pub fn parse_all<M: Message>(bytes: &[u8]) -> Vec<M> {
+    let mut input = CodedInputStream::from_bytes(bytes);
+    let mut messages = Vec::new();
+    while !input.eof().unwrap() {
+        messages.push(input.read_message().unwrap());
+    }
+    messages
+}
+
+/// A successful answer to a connection request.
+pub fn connection_response() -> krpc::ConnectionResponse {
+    let mut response = krpc::ConnectionResponse::new();
+    response.set_client_identifier(vec![1, 2, 3]);
+    response
+}
+
+/// A procedure result holding the given value.
+pub fn result(value: Vec<u8>) -> krpc::ProcedureResult {
+    let mut result = krpc::ProcedureResult::new();
+    result.set_value(value);
+    result
+}
+
+/// A stream update of a single stream.
+pub fn stream_update(id: u64, value: Vec<u8>) -> krpc::StreamUpdate {
+    let mut result = krpc::StreamResult::new();
+    result.set_id(id);
+    result.mut_result().set_value(value);
+    let mut update = krpc::StreamUpdate::new();
+    update.mut_results().push(result);
+    update
+}
+
+/// A procedure result reporting the given error.
+pub fn error_result(description: &str) -> krpc::ProcedureResult {
+    let mut result = krpc::ProcedureResult::new();
+    result.mut_error().set_description(description.to_string());
+    result
+}
+
+type Handler = Box<dyn FnMut(&krpc::ProcedureCall) -> krpc::ProcedureResult + Send>;
+
+/// An in-memory stand-in for the kRPC server. It answers each call with a handler, records the
+/// requests it receives and hands out the stream updates pushed by the test to the stream
+/// clients. Connect clients to it through [`Server::transport`].
+#[derive(Clone)]
+pub struct Server {
+    inner: Arc<(Mutex<ServerState>, Condvar)>,
+}
+
+struct ServerState {
+    handler: Handler,
+    requests: Vec<krpc::Request>,
+    updates: VecDeque<krpc::StreamUpdate>,
+    /// Bumped by `disconnect`. Transports opened before fail.
+    generation: u64,
+    handshakes: Vec<krpc::ConnectionRequest_Type>,
+    /// Whether the server holds back its answers. Reads time out meanwhile.
+    stalled: bool,
+}
+
+impl Server {
+    pub fn new(
+        handler: impl FnMut(&krpc::ProcedureCall) -> krpc::ProcedureResult + Send + 'static,
+    ) -> Self {
+        let state = ServerState {
+            handler: Box::new(handler),
+            requests: Vec::new(),
+            updates: VecDeque::new(),
+            generation: 0,
+            handshakes: Vec::new(),
+            stalled: false,
+        };
+        Server {
+            inner: Arc::new((Mutex::new(state), Condvar::new())),
+        }
+    }
+
+    /// A server streaming the procedures to which `stream_id` gives an id, and refusing to
+    /// stream the others. Other calls get an empty result.
+    pub fn streaming(mut stream_id: impl FnMut(&str) -> Option<u64> + Send + 'static) -> Self {
+        Server::new(move |call| match call.get_procedure() {
+            "AddStream" => {
+                let streamed = argument::<krpc::ProcedureCall>(call, 0).unwrap();
+                match stream_id(streamed.get_procedure()) {
+                    Some(id) => {
+                        let mut stream = krpc::Stream::new();
+                        stream.set_id(id);
+                        result(stream.write_to_bytes().unwrap())
+                    }
+                    None => error_result("no such object"),
+                }
+            }
+            _ => result(Vec::new()),
+        })
+    }
+
+    /// An RPC client and a stream client connected to the server.
+    pub fn clients(&self, name: &str) -> (RPCClient<MockTransport>, StreamClient<MockTransport>) {
+        let client = RPCClient::with_transport(name, self.transport()).unwrap();
+        let stream_client = StreamClient::with_transport(&client, self.transport()).unwrap();
+        (client, stream_client)
+    }
+
+    /// A new connection to the server.
+    pub fn transport(&self) -> MockTransport {
+        MockTransport {
+            server: self.clone(),
+            generation: self.state().generation,
+            pending: VecDeque::new(),
+            kind: None,
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, ServerState> {
+        self.inner.0.lock().unwrap()
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<krpc::Request> {
+        self.state().requests.clone()
+    }
+
+    /// The calls received so far, in order, whatever the request they were in.
+    pub fn calls(&self) -> Vec<krpc::ProcedureCall> {
+        let state = self.state();
+        let calls = state.requests.iter().flat_map(|r| r.get_calls().iter());
+        calls.cloned().collect()
+    }
+
+    /// The type of every connection accepted so far.
+    pub fn handshakes(&self) -> Vec<krpc::ConnectionRequest_Type> {
+        self.state().handshakes.clone()
+    }
+
+    /// Queues an update for the stream clients.
+    pub fn push_update(&self, update: krpc::StreamUpdate) {
+        self.state().updates.push_back(update);
+        self.inner.1.notify_all();
+    }
+
+    /// Drops every open connection. New transports can still connect.
+    pub fn disconnect(&self) {
+        self.state().generation += 1;
+        self.inner.1.notify_all();
+    }
+
+    pub fn set_stalled(&self, stalled: bool) {
+        self.state().stalled = stalled;
+        self.inner.1.notify_all();
+    }
+}
+
+/// A connection to a [`Server`].
+pub struct MockTransport {
+    server: Server,
+    generation: u64,
+    /// Responses not read yet.
+    pending: VecDeque<krpc::Response>,
+    kind: Option<krpc::ConnectionRequest_Type>,
+}
+
+impl std::fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("generation", &self.generation)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+fn io_error(kind: std::io::ErrorKind) -> ProtobufError {
+    std::io::Error::new(kind, "mock transport").into()
+}
+
+impl Transport for MockTransport {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), ProtobufError> {
+        let mut state = self.server.state();
+        if state.generation != self.generation {
+            return Err(io_error(std::io::ErrorKind::BrokenPipe));
+        }
+        let request = krpc::Request::parse_from_bytes(frame)?;
+        let mut response = krpc::Response::new();
+        for call in request.get_calls() {
+            let result = (state.handler)(call);
+            response.mut_results().push(result);
+        }
+        state.requests.push(request);
+        self.pending.push_back(response);
+        Ok(())
+    }
+
+    fn recv_frame(&mut self) -> Result<Vec<u8>, ProtobufError> {
+        let (lock, condvar) = &*self.server.inner;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if state.generation != self.generation {
+                return Err(io_error(std::io::ErrorKind::ConnectionReset));
+            }
+            if state.stalled {
+                return Err(io_error(std::io::ErrorKind::TimedOut));
+            }
+            if self.kind == Some(krpc::ConnectionRequest_Type::STREAM) {
+                if let Some(update) = state.updates.pop_front() {
+                    return update.write_to_bytes();
+                }
+            } else {
+                return match self.pending.pop_front() {
+                    Some(response) => response.write_to_bytes(),
+                    None => Err(io_error(std::io::ErrorKind::UnexpectedEof)),
+                };
+            }
+            let (guard, wait) = condvar.wait_timeout(state, Duration::from_secs(5)).unwrap();
+            state = guard;
+            if wait.timed_out() {
+                return Err(io_error(std::io::ErrorKind::TimedOut));
+            }
+        }
+    }
+
+    fn handshake(
+        &mut self,
+        request: &krpc::ConnectionRequest,
+    ) -> Result<krpc::ConnectionResponse, ProtobufError> {
+        let mut state = self.server.state();
+        if state.generation != self.generation {
+            return Err(io_error(std::io::ErrorKind::ConnectionRefused));
+        }
+        state.handshakes.push(request.get_field_type());
+        self.kind = Some(request.get_field_type());
+        Ok(connection_response())
+    }
+}
+
+/// A call to a procedure of the `Test` service, without arguments.
+pub fn call<T: RPCExtractable>(procedure: &str) -> CallHandle<T> {
+    let mut call = krpc::ProcedureCall::new();
+    call.set_service(String::from("Test"));
+    call.set_procedure(procedure.to_string());
+    CallHandle::new(call)
+}
+
+/// A type without type parameters, e.g. `DOUBLE`.
+pub fn scalar(code: krpc::Type_TypeCode) -> krpc::Type {
+    let mut ty = krpc::Type::new();
+    ty.set_code(code);
+    ty
+}
+
+/// Decodes the argument of the call at the given position.
+pub fn argument<T: RPCExtractable>(call: &krpc::ProcedureCall, position: u32) -> Option<T> {
+    let arg = call
+        .get_arguments()
+        .iter()
+        .find(|a| a.position == position)?;
+    let mut input = CodedInputStream::from_bytes(arg.get_value());
+    Some(T::extract_value(&mut input).unwrap())
+}
+
+/// Waits for up to 5 seconds for the condition to hold.
+pub fn eventually(condition: impl Fn() -> bool) -> bool {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while std::time::Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
@@ -0,0 +1,64 @@
+//! Checks that a Connection removes the streams it created when it goes away.
+mod common;
+
+use common::{argument, call, MockTransport, Server};
+
+use krpc_mars::Connection;
+
+/// A server giving increasing ids to the streams, which refuses to stream `get_Missing`.
+fn server() -> Server {
+    let mut last_id = 0;
+    Server::streaming(move |procedure| {
+        if procedure == "get_Missing" {
+            return None;
+        }
+        last_id += 1;
+        Some(last_id)
+    })
+}
+
+fn connect(server: &Server) -> Connection<MockTransport> {
+    let (client, stream_client) = server.clients("connection");
+    Connection::from_clients(client, stream_client)
+}
+
+/// The ids of the streams removed on the server, sorted.
+fn removed(server: &Server) -> Vec<u64> {
+    let mut ids: Vec<u64> = server
+        .calls()
+        .iter()
+        .filter(|call| call.get_procedure() == "RemoveStream")
+        .map(|call| argument::<u64>(call, 0).unwrap())
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn drop_removes_remaining_streams() {
+    let server = server();
+    let mut conn = connect(&server);
+
+    conn.add_stream(&call::<f64>("get_A")).unwrap();
+    let b = conn.add_stream(&call::<f64>("get_B")).unwrap();
+    conn.add_stream(&call::<f64>("get_C")).unwrap();
+    assert!(conn.add_stream(&call::<f64>("get_Missing")).is_err());
+    conn.remove_stream(b).unwrap();
+    assert_eq!(removed(&server), [2]);
+
+    drop(conn);
+    assert_eq!(removed(&server), [1, 2, 3]);
+}
+
+#[test]
+fn close_removes_streams_once() {
+    let server = server();
+    let mut conn = connect(&server);
+
+    conn.add_stream(&call::<f64>("get_A")).unwrap();
+    conn.add_stream(&call::<f64>("get_B")).unwrap();
+    conn.close().unwrap();
+
+    assert_eq!(removed(&server), [1, 2]);
+    assert_eq!(server.requests().len(), 3);
+}
@@ -0,0 +1,48 @@
+//! Checks the typed bindings of the KRPC service against its definition.
+mod common;
+
+use common::result;
+
+use krpc_mars::codec::{self, RPCEncodable};
+use krpc_mars::core::GameScene;
+use krpc_mars::error::RPCError;
+use krpc_mars::krpc::Procedure_GameScene;
+use krpc_mars::protobuf::ProtobufEnum;
+
+#[test]
+fn game_scenes_match_the_service_definition() {
+    let expected = [
+        (Procedure_GameScene::SPACE_CENTER, GameScene::SpaceCenter),
+        (Procedure_GameScene::FLIGHT, GameScene::Flight),
+        (
+            Procedure_GameScene::TRACKING_STATION,
+            GameScene::TrackingStation,
+        ),
+        (Procedure_GameScene::EDITOR_VAB, GameScene::EditorVAB),
+        (Procedure_GameScene::EDITOR_SPH, GameScene::EditorSPH),
+        (
+            Procedure_GameScene::MISSION_BUILDER,
+            GameScene::MissionBuilder,
+        ),
+    ];
+    assert_eq!(Procedure_GameScene::values().len(), expected.len());
+
+    for (definition, scene) in expected {
+        let decoded = codec::extract_result::<GameScene>(&result(
+            definition.value().encode_to_bytes().unwrap(),
+        ));
+        assert_eq!(decoded.unwrap(), scene);
+        // The variants are named after the ones of the definition.
+        assert_eq!(
+            format!("{:?}", definition).replace('_', "").to_lowercase(),
+            format!("{:?}", scene).to_lowercase()
+        );
+    }
+}
+
+#[test]
+fn unknown_game_scene() {
+    let e =
+        codec::extract_result::<GameScene>(&result(6i32.encode_to_bytes().unwrap())).unwrap_err();
+    assert!(matches!(e, RPCError::Decode { .. }), "{:?}", e);
+}
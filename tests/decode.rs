@@ -0,0 +1,123 @@
+//! Checks that malformed values are reported as errors instead of panicking.
+mod common;
+
+use common::result;
+
+use krpc_mars::codec::{self, RPCEncodable, Value};
+use krpc_mars::error::RPCError;
+use krpc_mars::krpc;
+use krpc_mars::krpc::Type_TypeCode as Code;
+use krpc_mars::protobuf::Message;
+
+use std::collections::{HashMap, HashSet};
+
+fn tuple(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut tuple = krpc::Tuple::new();
+    tuple.set_items(items.into());
+    tuple.write_to_bytes().unwrap()
+}
+
+fn list(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut list = krpc::List::new();
+    list.set_items(items.into());
+    list.write_to_bytes().unwrap()
+}
+
+fn decode_error(e: RPCError) -> (String, String, String) {
+    match e {
+        RPCError::Decode {
+            expected,
+            found,
+            path,
+        } => (expected, found, path),
+        e => panic!("not a decoding error: {:?}", e),
+    }
+}
+
+#[test]
+fn short_tuple() {
+    let bytes = tuple(vec![1.0f64.encode_to_bytes().unwrap()]);
+    let e = codec::extract_result::<(f64, f64)>(&result(bytes)).unwrap_err();
+    let (expected, found, path) = decode_error(e);
+    assert_eq!(expected, "a tuple of 2 items");
+    assert_eq!(found, "1 items");
+    assert_eq!(path, "");
+}
+
+#[test]
+fn extra_tuple_items() {
+    let bytes = tuple(vec![
+        1.0f64.encode_to_bytes().unwrap(),
+        2.0f64.encode_to_bytes().unwrap(),
+        3.0f64.encode_to_bytes().unwrap(),
+    ]);
+    let e = codec::extract_result::<(f64, f64)>(&result(bytes)).unwrap_err();
+    assert_eq!(decode_error(e).1, "3 items");
+}
+
+#[test]
+fn nested_error_path() {
+    // The third item of the list in the second item of the tuple is truncated.
+    let items = list(vec![
+        1.0f64.encode_to_bytes().unwrap(),
+        2.0f64.encode_to_bytes().unwrap(),
+        vec![0, 0, 0],
+    ]);
+    let bytes = tuple(vec![String::from("a").encode_to_bytes().unwrap(), items]);
+
+    let e = codec::extract_result::<(String, Vec<f64>)>(&result(bytes.clone())).unwrap_err();
+    let (expected, _, path) = decode_error(e);
+    assert_eq!(expected, "f64");
+    assert_eq!(path, ".1[2]");
+
+    let mut double = krpc::Type::new();
+    double.set_code(Code::DOUBLE);
+    let mut list_type = krpc::Type::new();
+    list_type.set_code(Code::LIST);
+    list_type.mut_types().push(double);
+    let mut string = krpc::Type::new();
+    string.set_code(Code::STRING);
+    let mut tuple_type = krpc::Type::new();
+    tuple_type.set_code(Code::TUPLE);
+    tuple_type.set_types(vec![string, list_type].into());
+
+    let e = Value::decode(&tuple_type, &bytes).unwrap_err();
+    let (expected, _, path) = decode_error(e);
+    assert_eq!(expected, "DOUBLE");
+    assert_eq!(path, ".1[2]");
+}
+
+/// A small deterministic generator, so that failures can be reproduced.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self) -> Vec<u8> {
+        let len = (self.next() % 64) as usize;
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+#[test]
+fn random_bytes_never_panic() {
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    for _ in 0..10_000 {
+        let result = result(rng.bytes());
+        let _ = codec::extract_result::<f64>(&result);
+        let _ = codec::extract_result::<String>(&result);
+        let _ = codec::extract_result::<Option<String>>(&result);
+        let _ = codec::extract_result::<codec::Bytes>(&result);
+        let _ = codec::extract_result::<(f64, f64, f64)>(&result);
+        let _ = codec::extract_result::<Vec<(u32, String)>>(&result);
+        let _ = codec::extract_result::<HashSet<i64>>(&result);
+        let _ = codec::extract_result::<HashMap<String, Vec<bool>>>(&result);
+        let _ = codec::extract_result::<krpc::Services>(&result);
+        let _ = codec::extract_result::<krpc_mars::stream::StreamHandle<f64>>(&result);
+    }
+}
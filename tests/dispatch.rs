@@ -0,0 +1,53 @@
+//! Runs a StreamDispatcher against an in-memory server.
+mod common;
+
+use common::{eventually, stream_update, Server};
+
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::StreamDispatcher;
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+fn spawn(server: &Server) -> StreamDispatcher {
+    let (_, stream_client) = server.clients("dispatch");
+    StreamDispatcher::spawn(stream_client)
+}
+
+#[test]
+fn values_go_to_their_subscribers() {
+    let server = Server::new(|_| unreachable!());
+    let dispatcher = spawn(&server);
+    let a = dispatcher.subscribe(StreamHandle::<f64>::new(1));
+    let b = dispatcher.subscribe(StreamHandle::<f64>::new(2));
+
+    server.push_update(stream_update(1, 1.0f64.encode_to_bytes().unwrap()));
+    assert_eq!(a.recv().unwrap().unwrap(), 1.0);
+    assert!(matches!(
+        b.recv_timeout(Duration::from_millis(10)),
+        Err(RecvTimeoutError::Timeout)
+    ));
+
+    // A late subscriber gets the current value first.
+    let late = dispatcher.subscribe(StreamHandle::<f64>::new(1));
+    assert_eq!(late.recv().unwrap().unwrap(), 1.0);
+}
+
+#[test]
+fn failure_closes_all_subscriptions() {
+    let server = Server::new(|_| unreachable!());
+    let dispatcher = spawn(&server);
+    let a = dispatcher.subscribe(StreamHandle::<f64>::new(1));
+    let b = dispatcher.subscribe(StreamHandle::<bool>::new(2));
+
+    server.disconnect();
+    assert!(a.recv().is_err());
+    assert!(b.recv().is_err());
+    // The thread may still be running, but the subscription is closed as well.
+    let late = dispatcher.subscribe(StreamHandle::<f64>::new(1));
+    assert!(late.recv().is_err());
+
+    assert!(eventually(|| !dispatcher.is_running()));
+    assert!(dispatcher.take_error().is_some());
+}
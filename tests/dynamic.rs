@@ -0,0 +1,250 @@
+//! Calls procedures by name, against the schema of an in-memory server.
+mod common;
+
+use common::{argument, error_result, result, scalar, Server};
+
+use krpc_mars::client::CallHandle;
+use krpc_mars::codec::{RPCEncodable, Value};
+use krpc_mars::error::RPCError;
+use krpc_mars::krpc;
+use krpc_mars::RPCClient;
+
+use protobuf::Message;
+
+fn parameter(name: &str, code: krpc::Type_TypeCode, default: Option<Vec<u8>>) -> krpc::Parameter {
+    let mut parameter = krpc::Parameter::new();
+    parameter.set_name(name.to_string());
+    parameter.set_field_type(scalar(code));
+    if let Some(default) = default {
+        parameter.set_default_value(default);
+    }
+    parameter
+}
+
+/// The schema of the server: `Test.Add(a: SINT32, b: SINT32 = 10) -> SINT32`,
+/// `Test.Echo(text: STRING = null) -> STRING`, `Test.get_Target() -> Test.Vessel`, which
+/// returns null, and `Test.set_Target(value: Test.Vessel = null)`.
+fn services() -> krpc::Services {
+    let mut add = krpc::Procedure::new();
+    add.set_name(String::from("Add"));
+    add.mut_parameters()
+        .push(parameter("a", krpc::Type_TypeCode::SINT32, None));
+    add.mut_parameters().push(parameter(
+        "b",
+        krpc::Type_TypeCode::SINT32,
+        Some(10.encode_to_bytes().unwrap()),
+    ));
+    add.set_return_type(scalar(krpc::Type_TypeCode::SINT32));
+
+    // A null default value is encoded as no bytes at all, which the schema can't tell apart from
+    // no default value.
+    let mut echo = krpc::Procedure::new();
+    echo.set_name(String::from("Echo"));
+    echo.mut_parameters()
+        .push(parameter("text", krpc::Type_TypeCode::STRING, None));
+    echo.set_return_type(scalar(krpc::Type_TypeCode::STRING));
+    echo.set_return_is_nullable(true);
+
+    let mut target = krpc::Procedure::new();
+    target.set_name(String::from("get_Target"));
+    let mut vessel = scalar(krpc::Type_TypeCode::CLASS);
+    vessel.set_service(String::from("Test"));
+    vessel.set_name(String::from("Vessel"));
+    target.set_return_type(vessel.clone());
+    target.set_return_is_nullable(true);
+
+    let mut set_target = krpc::Procedure::new();
+    set_target.set_name(String::from("set_Target"));
+    let mut value = krpc::Parameter::new();
+    value.set_name(String::from("value"));
+    value.set_field_type(vessel);
+    set_target.mut_parameters().push(value);
+
+    let mut service = krpc::Service::new();
+    service.set_name(String::from("Test"));
+    service.mut_procedures().push(add);
+    service.mut_procedures().push(echo);
+    service.mut_procedures().push(target);
+    service.mut_procedures().push(set_target);
+    let mut services = krpc::Services::new();
+    services.mut_services().push(service);
+    services
+}
+
+/// A server providing `services()`. It requires every argument, as defaults are filled in by the
+/// client.
+fn server() -> Server {
+    Server::new(|call| match call.get_procedure() {
+        "GetServices" => result(services().write_to_bytes().unwrap()),
+        "get_Target" => result(0u64.encode_to_bytes().unwrap()),
+        "set_Target" => match argument::<u64>(call, 0) {
+            Some(_) => result(vec![]),
+            None => error_result("missing argument"),
+        },
+        "Add" => match (argument::<i32>(call, 0), argument::<i32>(call, 1)) {
+            (Some(a), Some(b)) => result((a + b).encode_to_bytes().unwrap()),
+            _ => error_result("missing argument"),
+        },
+        _ => match argument::<Option<String>>(call, 0) {
+            Some(text) => result(text.encode_to_bytes().unwrap()),
+            None => error_result("missing argument"),
+        },
+    })
+}
+
+fn add() -> CallHandle<i32> {
+    let mut call = krpc::ProcedureCall::new();
+    call.set_service(String::from("Test"));
+    call.set_procedure(String::from("Add"));
+    CallHandle::new(call)
+}
+
+#[test]
+fn call_with_all_arguments() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+
+    let sum = client
+        .call_dynamic("Test", "Add", &[Value::SInt32(2), Value::SInt32(3)])
+        .unwrap();
+    assert_eq!(sum, Value::SInt32(5));
+}
+
+#[test]
+fn missing_arguments_take_their_default() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+
+    let sum = client
+        .call_dynamic("Test", "Add", &[Value::SInt32(2)])
+        .unwrap();
+    assert_eq!(sum, Value::SInt32(12));
+}
+
+#[test]
+fn null_default_values() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+
+    let text = client.call_dynamic("Test", "Echo", &[]).unwrap();
+    assert_eq!(text, Value::None);
+    let echo = server.calls().pop().unwrap();
+    assert_eq!(echo.get_arguments().len(), 1);
+    assert!(echo.get_arguments()[0].get_value().is_empty());
+
+    // A null object is encoded as the id 0.
+    client.call_dynamic("Test", "set_Target", &[]).unwrap();
+    let set_target = server.calls().pop().unwrap();
+    assert_eq!(set_target.get_arguments()[0].get_value(), [0]);
+
+    // An empty default value isn't valid for other types: their arguments are still required.
+    let e = client.call_dynamic("Test", "Add", &[]).unwrap_err();
+    assert!(
+        matches!(e, RPCError::InvalidArgument { position: 0, .. }),
+        "{:?}",
+        e
+    );
+}
+
+#[test]
+fn null_objects() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+
+    let target = client.call_dynamic("Test", "get_Target", &[]).unwrap();
+    assert_eq!(target, Value::None);
+}
+
+#[test]
+fn call_handle_arguments() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+    let procedure = client.procedure("Test", "Add").unwrap().clone();
+
+    let call = add().with_arg(0, &1).with_arg(1, &2);
+    assert_eq!(client.mk_call(&call).unwrap(), 3);
+
+    // Overrides the second argument only.
+    let call = call.with_arg(1, &5);
+    assert_eq!(client.mk_call(&call).unwrap(), 6);
+    assert_eq!(server.calls().pop().unwrap().get_arguments().len(), 2);
+
+    // Defaults don't replace the arguments the call has.
+    let call = call.with_defaults(&procedure);
+    assert_eq!(client.mk_call(&call).unwrap(), 6);
+
+    let call = call.without_arg(1);
+    assert!(matches!(
+        client.mk_call(&call),
+        Err(RPCError::KRPCRequestErr(_))
+    ));
+    let call = call.with_defaults(&procedure);
+    assert_eq!(client.mk_call(&call).unwrap(), 11);
+
+    // Parameters without a default value are left out.
+    let call = call.without_arg(0).with_defaults(&procedure);
+    assert!(client.mk_call(&call).is_err());
+    let add = server.calls().pop().unwrap();
+    let positions: Vec<u32> = add.get_arguments().iter().map(|a| a.position).collect();
+    assert_eq!(positions, [1]);
+}
+
+#[test]
+fn unknown_procedure() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+
+    for (service, procedure) in [("Test", "Sub"), ("Other", "Add")] {
+        let e = client.call_dynamic(service, procedure, &[]).unwrap_err();
+        assert!(
+            matches!(&e, RPCError::UnknownProcedure { service: s, procedure: p }
+                if s == service && p == procedure),
+            "{:?}",
+            e
+        );
+    }
+    // The schema is fetched once.
+    assert_eq!(server.calls().len(), 1);
+}
+
+#[test]
+fn invalid_arguments() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+
+    let too_many = [Value::SInt32(1), Value::SInt32(2), Value::SInt32(3)];
+    let e = client.call_dynamic("Test", "Add", &too_many).unwrap_err();
+    assert!(
+        matches!(&e, RPCError::InvalidArgument { procedure, position: 2, .. }
+            if procedure == "Test.Add"),
+        "{:?}",
+        e
+    );
+
+    let e = client.call_dynamic("Test", "Add", &[]).unwrap_err();
+    assert!(
+        matches!(&e, RPCError::InvalidArgument { position: 0, reason, .. }
+            if reason == "missing argument a"),
+        "{:?}",
+        e
+    );
+
+    // Nothing but the schema request reached the server.
+    assert_eq!(server.calls().len(), 1);
+}
+
+#[test]
+fn arguments_are_type_checked() {
+    let server = server();
+    let mut client = RPCClient::with_transport("dynamic", server.transport()).unwrap();
+
+    let args = [Value::SInt32(1), Value::String(String::from("2"))];
+    let e = client.call_dynamic("Test", "Add", &args).unwrap_err();
+    assert!(
+        matches!(&e, RPCError::TypeMismatch { expected, found }
+            if expected == "SINT32" && found == "STRING"),
+        "{:?}",
+        e
+    );
+    assert_eq!(server.calls().len(), 1);
+}
@@ -0,0 +1,99 @@
+//! Waits for events from a local stand-in for the stream server.
+mod common;
+
+use common::{connection_response, stream_update, Server};
+
+use krpc_mars::codec::{RPCEncodable, RPCExtractable};
+use krpc_mars::error::RPCError;
+use krpc_mars::krpc;
+use krpc_mars::protobuf::{CodedInputStream, Message};
+use krpc_mars::{EventHandle, RPCClient, StreamClient};
+
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::time::Duration;
+
+const EVENT_STREAM: u64 = 3;
+
+fn event() -> EventHandle {
+    let mut event = krpc::Event::new();
+    event.mut_stream().set_id(EVENT_STREAM);
+    let bytes = event.write_to_bytes().unwrap();
+    let mut input = CodedInputStream::from_bytes(&bytes);
+    EventHandle::extract_value(&mut input).unwrap()
+}
+
+/// Connects a stream client to a server which sends the given updates of the event stream, then
+/// waits for `done` before closing the connection.
+fn connect(values: Vec<bool>, done: mpsc::Receiver<()>) -> StreamClient {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        let (mut sock, _) = listener.accept().unwrap();
+        CodedInputStream::new(&mut sock)
+            .read_message::<krpc::ConnectionRequest>()
+            .unwrap();
+        connection_response()
+            .write_length_delimited_to_writer(&mut sock)
+            .unwrap();
+        for value in values {
+            stream_update(EVENT_STREAM, value.encode_to_bytes().unwrap())
+                .write_length_delimited_to_writer(&mut sock)
+                .unwrap();
+        }
+        let _ = done.recv();
+    });
+
+    let client =
+        RPCClient::with_transport("event", Server::new(|_| unreachable!()).transport()).unwrap();
+    let stream_client = StreamClient::connect(&client, addr).unwrap();
+    stream_client
+        .transport()
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .unwrap();
+    stream_client
+}
+
+fn read_timeout(stream_client: &StreamClient) -> Option<Duration> {
+    stream_client.transport().get_ref().read_timeout().unwrap()
+}
+
+#[test]
+fn event_fires() {
+    let (_done, done_rx) = mpsc::channel();
+    let mut stream_client = connect(vec![false, true], done_rx);
+
+    let update = stream_client
+        .wait_for_event(&event(), Some(Duration::from_secs(5)))
+        .unwrap();
+    assert_eq!(update.get_result(&event().stream()).unwrap(), Some(true));
+    assert_eq!(read_timeout(&stream_client), Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn timeout_restores_read_timeout() {
+    let (_done, done_rx) = mpsc::channel();
+    let mut stream_client = connect(vec![false], done_rx);
+
+    let e = stream_client
+        .wait_for_event(&event(), Some(Duration::from_millis(50)))
+        .unwrap_err();
+    assert!(matches!(e, RPCError::Timeout));
+    assert_eq!(read_timeout(&stream_client), Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn error_restores_read_timeout() {
+    let (done, done_rx) = mpsc::channel();
+    let mut stream_client = connect(vec![false], done_rx);
+    done.send(()).unwrap();
+
+    // The server closes the connection before the event fires.
+    let e = stream_client
+        .wait_for_event(&event(), Some(Duration::from_secs(5)))
+        .unwrap_err();
+    assert!(!matches!(e, RPCError::Timeout));
+    assert_eq!(read_timeout(&stream_client), Some(Duration::from_secs(30)));
+}
@@ -0,0 +1,87 @@
+//! Builds expressions against an in-memory server.
+mod common;
+
+use common::{argument, result, Server};
+
+use krpc_mars::client::CallHandle;
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::event::Expression;
+use krpc_mars::krpc;
+use krpc_mars::{Expr, RPCClient};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A server creating expression objects with increasing ids, from 100.
+fn server() -> Server {
+    let next_id = AtomicU64::new(100);
+    Server::new(move |call| {
+        assert!(call.get_procedure().starts_with("Expression_static_"));
+        let id = next_id.fetch_add(1, Ordering::Relaxed);
+        result(id.encode_to_bytes().unwrap())
+    })
+}
+
+fn altitude() -> CallHandle<f64> {
+    let mut call = krpc::ProcedureCall::new();
+    call.set_service(String::from("SpaceCenter"));
+    call.set_procedure(String::from("Flight_get_MeanAltitude"));
+    CallHandle::new(call)
+}
+
+/// The procedures called by a request, without their common prefix.
+fn procedures(request: &krpc::Request) -> Vec<&str> {
+    request
+        .get_calls()
+        .iter()
+        .map(|call| {
+            call.get_procedure()
+                .trim_start_matches("Expression_static_")
+        })
+        .collect()
+}
+
+/// The expression ids passed to the calls of a request.
+fn operands(request: &krpc::Request) -> Vec<Vec<u64>> {
+    request
+        .get_calls()
+        .iter()
+        .map(|call| {
+            (0..call.get_arguments().len() as u32)
+                .map(|position| argument::<u64>(call, position).unwrap())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn one_request_per_level() {
+    let server = server();
+    let mut client = RPCClient::with_transport("expr", server.transport()).unwrap();
+
+    // The altitude is shared by both comparisons.
+    let altitude = Expr::call(&altitude());
+    let condition = altitude.clone().greater_than(1.0) & altitude.less_than(5.0);
+    let expression = condition.build(&mut client).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(
+        procedures(&requests[0]),
+        ["Call", "ConstantDouble", "ConstantDouble"]
+    );
+    assert_eq!(procedures(&requests[1]), ["GreaterThan", "LessThan"]);
+    assert_eq!(operands(&requests[1]), [[100, 101], [100, 102]]);
+    assert_eq!(procedures(&requests[2]), ["And"]);
+    assert_eq!(operands(&requests[2]), [[103, 104]]);
+    assert_eq!(expression, Expression::new(105));
+}
+
+#[test]
+fn single_node() {
+    let server = server();
+    let mut client = RPCClient::with_transport("expr", server.transport()).unwrap();
+
+    let expression = Expr::constant(true).build(&mut client).unwrap();
+    assert_eq!(expression, Expression::new(100));
+    assert_eq!(procedures(&server.requests()[0]), ["ConstantBool"]);
+}
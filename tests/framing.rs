@@ -0,0 +1,72 @@
+//! Checks that no bytes are lost between length-delimited messages, however the transport splits
+//! them.
+mod common;
+
+use common::{connection_response, length_delimited, ByteStream};
+
+use krpc_mars::krpc;
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::transport::Framed;
+use krpc_mars::{RPCClient, StreamClient, Transport};
+
+/// The length-delimited encoding of an update of a single stream.
+fn stream_update(id: u64, value: u8) -> Vec<u8> {
+    length_delimited(&[&common::stream_update(id, vec![value])])
+}
+
+#[test]
+fn concatenated_updates_in_one_read() {
+    let client = RPCClient::with_transport(
+        "test",
+        Framed::new(ByteStream::new(vec![length_delimited(&[
+            &connection_response(),
+        ])])),
+    )
+    .unwrap();
+
+    // The handshake response and all the updates arrive at once.
+    let mut chunk = length_delimited(&[&connection_response()]);
+    for value in 1..=3 {
+        chunk.extend(stream_update(7, value));
+    }
+    let mut stream_client =
+        StreamClient::with_transport(&client, Framed::new(ByteStream::new(vec![chunk]))).unwrap();
+
+    let handle = StreamHandle::<u32>::new(7);
+    for value in 1..=3 {
+        let update = stream_client.recv_update().unwrap();
+        assert_eq!(update.get_result(&handle).unwrap(), Some(value));
+    }
+    assert!(stream_client.recv_update().is_err());
+}
+
+#[test]
+fn message_split_across_reads() {
+    let mut bytes = stream_update(1, 10);
+    bytes.extend(stream_update(2, 20));
+
+    // One byte per read, so that both the length prefix and the payload are split.
+    let chunks = bytes.into_iter().map(|byte| vec![byte]).collect();
+    let mut transport = Framed::new(ByteStream::new(chunks));
+
+    let first = transport.recv_message::<krpc::StreamUpdate>().unwrap();
+    assert_eq!(first.get_results()[0].get_id(), 1);
+    let second = transport.recv_message::<krpc::StreamUpdate>().unwrap();
+    assert_eq!(second.get_results()[0].get_id(), 2);
+}
+
+#[test]
+fn message_straddling_reads() {
+    let mut bytes = stream_update(1, 10);
+    bytes.extend(stream_update(2, 20));
+    bytes.extend(stream_update(3, 30));
+
+    // The second read ends in the middle of the last message.
+    let (head, tail) = bytes.split_at(bytes.len() - 2);
+    let mut transport = Framed::new(ByteStream::new(vec![head.to_vec(), tail.to_vec()]));
+
+    for id in 1..=3 {
+        let update = transport.recv_message::<krpc::StreamUpdate>().unwrap();
+        assert_eq!(update.get_results()[0].get_id(), id);
+    }
+}
@@ -0,0 +1,97 @@
+//! Checks that the limits set on a client are enforced on the messages and values it receives.
+mod common;
+
+use common::{connection_response, stream_update, ByteStream};
+
+use krpc_mars::codec::{Limits, RPCEncodable};
+use krpc_mars::error::RPCError;
+use krpc_mars::protobuf::Message;
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::transport::Framed;
+use krpc_mars::{RPCClient, StreamClient};
+
+fn replay(messages: &[&dyn Message]) -> Framed<ByteStream> {
+    Framed::new(ByteStream::from_messages(messages))
+}
+
+/// A stream client which receives a single update of stream 1.
+fn stream_client(value: Vec<u8>, limits: Limits) -> StreamClient<Framed<ByteStream>> {
+    let client = RPCClient::with_transport("test", replay(&[&connection_response()])).unwrap();
+
+    let transport = replay(&[&connection_response(), &stream_update(1, value)]);
+    let mut stream_client = StreamClient::with_transport(&client, transport).unwrap();
+    stream_client.set_limits(limits);
+    stream_client
+}
+
+fn limit_exceeded(e: RPCError) -> (&'static str, usize, usize) {
+    match e {
+        RPCError::LimitExceeded { what, found, max } => (what, found, max),
+        e => panic!("not a limit error: {:?}", e),
+    }
+}
+
+#[test]
+fn oversized_frame() {
+    // Only the length prefix of a 1 GiB response arrives: it must be rejected before the payload.
+    let mut bytes = connection_response()
+        .write_length_delimited_to_bytes()
+        .unwrap();
+    bytes.extend([0x80, 0x80, 0x80, 0x80, 0x04]);
+    let transport = Framed::new(ByteStream::new(vec![bytes]));
+
+    let mut client = RPCClient::with_transport("test", transport).unwrap();
+    client.set_limits(Limits {
+        max_frame_size: 1024,
+        ..Limits::default()
+    });
+
+    let e = client
+        .mk_call(&krpc_mars::core::get_client_name())
+        .unwrap_err();
+    assert_eq!(limit_exceeded(e), ("frame size", 1 << 30, 1024));
+}
+
+#[test]
+fn long_collection() {
+    let value = vec![1u32, 2, 3, 4, 5].encode_to_bytes().unwrap();
+    let limits = Limits {
+        max_collection_len: 3,
+        ..Limits::default()
+    };
+    let update = stream_client(value.clone(), limits).recv_update().unwrap();
+    let e = update
+        .get_result(&StreamHandle::<Vec<u32>>::new(1))
+        .unwrap_err();
+    assert_eq!(limit_exceeded(e), ("collection length", 5, 3));
+
+    let update = stream_client(value, Limits::default())
+        .recv_update()
+        .unwrap();
+    let values = update.get_result(&StreamHandle::<Vec<u32>>::new(1));
+    assert_eq!(values.unwrap(), Some(vec![1, 2, 3, 4, 5]));
+}
+
+#[test]
+fn deep_nesting() {
+    let value = vec![vec![vec![1u32]]].encode_to_bytes().unwrap();
+    let handle = StreamHandle::<Vec<Vec<Vec<u32>>>>::new(1);
+
+    let limits = Limits {
+        max_depth: 2,
+        ..Limits::default()
+    };
+    let update = stream_client(value.clone(), limits).recv_update().unwrap();
+    let e = update.get_result(&handle).unwrap_err();
+    assert_eq!(limit_exceeded(e), ("nesting depth", 3, 2));
+
+    let limits = Limits {
+        max_depth: 3,
+        ..Limits::default()
+    };
+    let update = stream_client(value, limits).recv_update().unwrap();
+    assert_eq!(
+        update.get_result(&handle).unwrap(),
+        Some(vec![vec![vec![1]]])
+    );
+}
@@ -0,0 +1,111 @@
+//! Sends several requests before reading their responses.
+mod common;
+
+use common::{call, connection_response, length_delimited, result, ByteStream, Server};
+
+use krpc_mars::client::Ticket;
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::error::RPCError;
+use krpc_mars::krpc;
+use krpc_mars::transport::Framed;
+use krpc_mars::{RPCClient, RPCRequest, Transport};
+
+/// A server answering each call with the name of its procedure.
+fn server() -> Server {
+    Server::new(|call| result(call.get_procedure().to_string().encode_to_bytes().unwrap()))
+}
+
+fn send<T: Transport>(client: &mut RPCClient<T>, procedure: &str) -> Ticket {
+    let mut request = RPCRequest::default();
+    request.add_call(&call::<String>(procedure));
+    client.send_request(request).unwrap()
+}
+
+fn recv<T: Transport>(client: &mut RPCClient<T>, ticket: Ticket) -> String {
+    let response = client.recv_response(ticket).unwrap();
+    call::<String>("").get_result(&response, 0).unwrap()
+}
+
+#[test]
+fn responses_in_order() {
+    let server = server();
+    let mut client = RPCClient::with_transport("pipelining", server.transport()).unwrap();
+
+    let tickets: Vec<Ticket> = ["A", "B", "C"]
+        .iter()
+        .map(|procedure| send(&mut client, procedure))
+        .collect();
+    let names: Vec<String> = tickets
+        .into_iter()
+        .map(|ticket| recv(&mut client, ticket))
+        .collect();
+    assert_eq!(names, ["A", "B", "C"]);
+}
+
+#[test]
+fn responses_out_of_order() {
+    let server = server();
+    let mut client = RPCClient::with_transport("pipelining", server.transport()).unwrap();
+
+    let a = send(&mut client, "A");
+    let b = send(&mut client, "B");
+    let c = send(&mut client, "C");
+    assert_eq!(recv(&mut client, c), "C");
+    assert_eq!(recv(&mut client, a), "A");
+    assert_eq!(recv(&mut client, b), "B");
+}
+
+#[test]
+fn dropped_tickets() {
+    let server = server();
+    let mut client = RPCClient::with_transport("pipelining", server.transport()).unwrap();
+
+    // Dropped before and after its response is read ahead.
+    drop(send(&mut client, "A"));
+    let b = send(&mut client, "B");
+    let c = send(&mut client, "C");
+    assert_eq!(recv(&mut client, c), "C");
+    drop(b);
+
+    let d = send(&mut client, "D");
+    assert_eq!(recv(&mut client, d), "D");
+}
+
+#[test]
+fn ticket_of_another_client() {
+    let server = server();
+    let mut client = RPCClient::with_transport("pipelining", server.transport()).unwrap();
+    let mut other = RPCClient::with_transport("other", server.transport()).unwrap();
+
+    let ticket = send(&mut other, "A");
+    let e = client.recv_response(ticket).unwrap_err();
+    assert!(matches!(e, RPCError::UnknownTicket));
+    assert_eq!(e.to_string(), "Unknown or already-consumed ticket");
+
+    // The ticket is given up, so the other client goes on with its next request.
+    let b = send(&mut other, "B");
+    assert_eq!(recv(&mut other, b), "B");
+}
+
+#[test]
+fn malformed_response() {
+    let mut response = krpc::Response::new();
+    response
+        .mut_results()
+        .push(result(String::from("B").encode_to_bytes().unwrap()));
+    let mut bytes = length_delimited(&[&connection_response()]);
+    // A frame holding a single byte which isn't a valid response.
+    bytes.extend([1, 0xff]);
+    bytes.extend(length_delimited(&[&response]));
+    let transport = Framed::new(ByteStream::new(vec![bytes]));
+    let mut client = RPCClient::with_transport("pipelining", transport).unwrap();
+
+    let a = send(&mut client, "A");
+    let b = send(&mut client, "B");
+    assert!(matches!(
+        client.recv_response(a),
+        Err(RPCError::ProtobufErr(_))
+    ));
+    // The malformed frame only takes the place of the first response.
+    assert_eq!(recv(&mut client, b), "B");
+}
@@ -0,0 +1,234 @@
+//! Runs the ReconnectingClient against an in-memory server which drops its connections.
+mod common;
+
+use common::{argument, call, MockTransport, Server};
+
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::error::{ConnectionError, RPCError};
+use krpc_mars::krpc;
+use krpc_mars::reconnect::{Backoff, Connector};
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::{RPCClient, ReconnectingClient, StreamClient};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+struct Mock(Server);
+
+impl Connector for Mock {
+    type Transport = MockTransport;
+
+    fn connect(&self) -> Result<RPCClient<MockTransport>, ConnectionError> {
+        RPCClient::with_transport("reconnect", self.0.transport())
+    }
+
+    fn connect_stream(
+        &self,
+        client: &RPCClient<MockTransport>,
+    ) -> Result<StreamClient<MockTransport>, ConnectionError> {
+        StreamClient::with_transport(client, self.0.transport())
+    }
+}
+
+/// A server whose streams get the id given by `ids` to the procedure they call. Streams of other
+/// procedures can't be created.
+fn server(ids: &Arc<Mutex<HashMap<&'static str, u64>>>) -> Server {
+    let ids = Arc::clone(ids);
+    Server::streaming(move |procedure| ids.lock().unwrap().get(procedure).copied())
+}
+
+fn connect(server: &Server) -> ReconnectingClient<Mock> {
+    let backoff = Backoff {
+        initial: Duration::from_millis(1),
+        max: Duration::from_millis(1),
+        max_attempts: Some(3),
+    };
+    ReconnectingClient::connect(Mock(server.clone()))
+        .unwrap()
+        .with_backoff(backoff)
+}
+
+fn update(values: &[(u64, f64)]) -> krpc::StreamUpdate {
+    let mut update = krpc::StreamUpdate::new();
+    for (id, value) in values {
+        let mut result = krpc::StreamResult::new();
+        result.set_id(*id);
+        result
+            .mut_result()
+            .set_value(value.encode_to_bytes().unwrap());
+        update.mut_results().push(result);
+    }
+    update
+}
+
+#[test]
+fn stream_ids_survive_reconnection() {
+    let ids = Arc::new(Mutex::new(HashMap::from([
+        ("get_A", 1),
+        ("get_B", 2),
+        ("get_D", 4),
+    ])));
+    let server = server(&ids);
+    let mut client = connect(&server);
+
+    let a = client
+        .mk_call(&call::<f64>("get_A").to_stream_paused())
+        .unwrap();
+    client.mk_call(&a.start()).unwrap();
+    client.mk_call(&a.set_rate(5.0)).unwrap();
+    let b = client
+        .mk_call(&call::<f64>("get_B").to_stream_paused())
+        .unwrap();
+    client.mk_call(&b.set_rate(2.0)).unwrap();
+    let d = client.mk_call(&call::<f64>("get_D").to_stream()).unwrap();
+
+    server.push_update(update(&[(1, 1.0), (2, 2.0), (4, 4.0)]));
+    let values = client.recv_update().unwrap();
+    assert_eq!(values.get_result(&a).unwrap(), Some(1.0));
+    assert_eq!(values.get_result(&b).unwrap(), Some(2.0));
+    assert_eq!(values.get_result(&d).unwrap(), Some(4.0));
+
+    // The server restarts and gives other ids to the streams.
+    *ids.lock().unwrap() = HashMap::from([("get_A", 7), ("get_B", 8), ("get_C", 1), ("get_D", 9)]);
+    let sent_before = server.requests().len();
+    server.disconnect();
+    server.push_update(update(&[(7, 3.0), (8, 4.0)]));
+
+    let values = client.recv_update().unwrap();
+    assert_eq!(values.get_result(&a).unwrap(), Some(3.0));
+    assert_eq!(values.get_result(&b).unwrap(), Some(4.0));
+    assert_eq!(server.handshakes().len(), 4);
+
+    // The streams with a rate are added paused, then get their rate, then `a` is started again.
+    // `d` is added running right away.
+    let requests = &server.requests()[sent_before..];
+    assert_eq!(requests.len(), 2);
+    let mut restored = HashMap::new();
+    for call in requests[0].get_calls() {
+        assert_eq!(call.get_procedure(), "AddStream");
+        let streamed = argument::<krpc::ProcedureCall>(call, 0).unwrap();
+        let start = argument::<bool>(call, 1).unwrap_or(true);
+        restored.insert(streamed.get_procedure().to_string(), start);
+    }
+    assert_eq!(
+        restored,
+        HashMap::from([
+            (String::from("get_A"), false),
+            (String::from("get_B"), false),
+            (String::from("get_D"), true)
+        ])
+    );
+    let mut configured: Vec<(String, u64)> = requests[1]
+        .get_calls()
+        .iter()
+        .map(|call| {
+            let id = argument::<u64>(call, 0).unwrap();
+            (call.get_procedure().to_string(), id)
+        })
+        .collect();
+    let start = configured
+        .iter()
+        .position(|(procedure, _)| procedure == "StartStream")
+        .unwrap();
+    let rate = configured
+        .iter()
+        .position(|c| *c == (String::from("SetStreamRate"), 7))
+        .unwrap();
+    assert!(rate < start);
+    configured.sort();
+    assert_eq!(
+        configured,
+        [
+            (String::from("SetStreamRate"), 7),
+            (String::from("SetStreamRate"), 8),
+            (String::from("StartStream"), 7)
+        ]
+    );
+    let rates: HashMap<u64, f32> = requests[1]
+        .get_calls()
+        .iter()
+        .filter(|call| call.get_procedure() == "SetStreamRate")
+        .map(|call| (argument(call, 0).unwrap(), argument(call, 1).unwrap()))
+        .collect();
+    assert_eq!(rates, HashMap::from([(7, 5.0), (8, 2.0)]));
+
+    // A new stream gets the id of `a` on the server, so the caller sees another one.
+    let c = client.mk_call(&call::<f64>("get_C").to_stream()).unwrap();
+    server.push_update(update(&[(1, 5.0)]));
+    let values = client.recv_update().unwrap();
+    assert_eq!(values.get_result(&c).unwrap(), Some(5.0));
+    assert_eq!(values.get_result(&a).unwrap(), None);
+
+    // Calls taking a stream id are sent with the id used by the server.
+    client.mk_call(&a.remove()).unwrap();
+    client.mk_call(&c.remove()).unwrap();
+    let removed: Vec<u64> = server
+        .calls()
+        .iter()
+        .filter(|call| call.get_procedure() == "RemoveStream")
+        .map(|call| argument::<u64>(call, 0).unwrap())
+        .collect();
+    assert_eq!(removed, [7, 1]);
+}
+
+#[test]
+fn unrestored_streams_stay_off_the_server() {
+    let ids = Arc::new(Mutex::new(HashMap::from([("get_A", 1), ("get_B", 2)])));
+    let server = server(&ids);
+    let mut client = connect(&server);
+    let a = client.mk_call(&call::<f64>("get_A").to_stream()).unwrap();
+    let b = client.mk_call(&call::<f64>("get_B").to_stream()).unwrap();
+
+    // After the restart, `a` can't be created and `b` gets the former id of `a`.
+    *ids.lock().unwrap() = HashMap::from([("get_B", 1)]);
+    server.disconnect();
+    server.push_update(update(&[(1, 3.0)]));
+    let values = client.recv_update().unwrap();
+    assert_eq!(values.get_result(&b).unwrap(), Some(3.0));
+    assert_eq!(values.get_result(&a).unwrap(), None);
+
+    let sent_before = server.calls().len();
+    assert!(matches!(
+        client.mk_call(&a.start()),
+        Err(RPCError::StreamNotRestored(1))
+    ));
+    assert!(matches!(
+        client.mk_call(&a.set_rate(1.0)),
+        Err(RPCError::StreamNotRestored(1))
+    ));
+    client.mk_call(&a.remove()).unwrap();
+    assert_eq!(server.calls().len(), sent_before);
+
+    // `b` is still known, and removing it reaches the server with its new id.
+    server.push_update(update(&[(1, 4.0)]));
+    let values = client.recv_update().unwrap();
+    assert_eq!(values.get_result(&b).unwrap(), Some(4.0));
+    client.mk_call(&b.remove()).unwrap();
+    let removed: Vec<u64> = server.calls()[sent_before..]
+        .iter()
+        .map(|call| argument::<u64>(call, 0).unwrap())
+        .collect();
+    assert_eq!(removed, [1]);
+}
+
+#[test]
+fn timeouts_do_not_reconnect() {
+    let ids = Arc::new(Mutex::new(HashMap::from([("get_A", 1)])));
+    let server = server(&ids);
+    let mut client = connect(&server);
+    let a: StreamHandle<f64> = client.mk_call(&call::<f64>("get_A").to_stream()).unwrap();
+
+    server.set_stalled(true);
+    assert!(matches!(client.recv_update(), Err(RPCError::Timeout)));
+    assert!(matches!(
+        client.mk_call(&a.set_rate(1.0)),
+        Err(RPCError::Timeout)
+    ));
+    assert_eq!(server.handshakes().len(), 2);
+
+    server.set_stalled(false);
+    server.push_update(update(&[(1, 1.0)]));
+    let values = client.recv_update().unwrap();
+    assert_eq!(values.get_result(&a).unwrap(), Some(1.0));
+}
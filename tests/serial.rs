@@ -0,0 +1,45 @@
+//! Runs the SerialIO client over an in-memory line.
+mod common;
+
+use common::{connection_response, parse_all, result, stream_update, ByteStream};
+
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::krpc;
+use krpc_mars::serial::SerialClient;
+use krpc_mars::stream::StreamHandle;
+
+#[test]
+fn multiplexed_round_trip() {
+    // An update arrives while the client waits for the response to its request.
+    let mut with_update = krpc::MultiplexedResponse::new();
+    with_update.set_stream_update(stream_update(4, 2.5f64.encode_to_bytes().unwrap()));
+    let mut with_response = krpc::MultiplexedResponse::new();
+    with_response
+        .mut_response()
+        .mut_results()
+        .push(result(String::from("serial").encode_to_bytes().unwrap()));
+
+    let line = ByteStream::from_messages(&[&connection_response(), &with_update, &with_response]);
+    let written = line.written();
+
+    let mut client = SerialClient::connect("serial", line).unwrap();
+    let name = client.mk_call(&krpc_mars::core::get_client_name()).unwrap();
+    assert_eq!(name, "serial");
+
+    let update = client.recv_update().unwrap();
+    assert_eq!(
+        update.get_result(&StreamHandle::<f64>::new(4)).unwrap(),
+        Some(2.5)
+    );
+
+    let sent = parse_all::<krpc::MultiplexedRequest>(&written.lock().unwrap());
+    assert_eq!(sent.len(), 2);
+    let connection = sent[0].get_connection_request();
+    assert_eq!(
+        connection.get_field_type(),
+        krpc::ConnectionRequest_Type::RPC
+    );
+    assert_eq!(connection.get_client_name(), "serial");
+    let call = &sent[1].get_request().get_calls()[0];
+    assert_eq!(call.get_procedure(), "GetClientName");
+}
@@ -0,0 +1,67 @@
+//! Runs a client with a read timeout against a server which doesn't answer in time.
+mod common;
+
+use common::{connection_response, result};
+
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::error::RPCError;
+use krpc_mars::krpc;
+use krpc_mars::protobuf::{CodedInputStream, Message};
+use krpc_mars::RPCClient;
+
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn read_request(sock: &mut TcpStream) -> krpc::Request {
+    CodedInputStream::new(sock).read_message().unwrap()
+}
+
+fn reply(sock: &mut TcpStream, name: &str) {
+    let mut response = krpc::Response::new();
+    let value = String::from(name).encode_to_bytes().unwrap();
+    response.mut_results().push(result(value));
+    response.write_length_delimited_to_writer(sock).unwrap();
+}
+
+#[test]
+fn late_response_is_discarded() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (timed_out_tx, timed_out) = mpsc::channel();
+
+    let server = std::thread::spawn(move || {
+        let (mut sock, _) = listener.accept().unwrap();
+        CodedInputStream::new(&mut sock)
+            .read_message::<krpc::ConnectionRequest>()
+            .unwrap();
+        connection_response()
+            .write_length_delimited_to_writer(&mut sock)
+            .unwrap();
+
+        // Only answer the first request once the client gave up on it.
+        read_request(&mut sock);
+        timed_out.recv().unwrap();
+        reply(&mut sock, "late");
+
+        read_request(&mut sock);
+        reply(&mut sock, "on time");
+    });
+
+    let mut client = RPCClient::builder()
+        .rpc_addr(&addr.to_string())
+        .read_timeout(Duration::from_millis(100))
+        .connect()
+        .unwrap();
+
+    let e = client
+        .mk_call(&krpc_mars::core::get_client_name())
+        .unwrap_err();
+    assert!(e.is_timeout());
+    assert!(matches!(e, RPCError::Timeout));
+    timed_out_tx.send(()).unwrap();
+
+    let name = client.mk_call(&krpc_mars::core::get_client_name()).unwrap();
+    assert_eq!(name, "on time");
+    server.join().unwrap();
+}
@@ -0,0 +1,40 @@
+//! Runs the clients over a transport chosen at runtime.
+mod common;
+
+use common::{result, stream_update, Server};
+
+use krpc_mars::codec::RPCEncodable;
+use krpc_mars::krpc;
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::{RPCClient, StreamClient, Transport};
+
+#[test]
+fn boxed_transport() {
+    let server = Server::new(|call| {
+        assert_eq!(call.get_procedure(), "GetClientName");
+        result(String::from("boxed").encode_to_bytes().unwrap())
+    });
+
+    let transport: Box<dyn Transport> = Box::new(server.transport());
+    let mut client = RPCClient::with_transport("boxed", transport).unwrap();
+    let name = client.mk_call(&krpc_mars::core::get_client_name()).unwrap();
+    assert_eq!(name, "boxed");
+
+    let transport: Box<dyn Transport> = Box::new(server.transport());
+    let mut stream_client = StreamClient::with_transport(&client, transport).unwrap();
+    server.push_update(stream_update(5, 1.5f64.encode_to_bytes().unwrap()));
+    let update = stream_client.recv_update().unwrap();
+    assert_eq!(
+        update.get_result(&StreamHandle::<f64>::new(5)).unwrap(),
+        Some(1.5)
+    );
+
+    assert_eq!(
+        server.handshakes(),
+        [
+            krpc::ConnectionRequest_Type::RPC,
+            krpc::ConnectionRequest_Type::STREAM
+        ]
+    );
+    assert_eq!(server.requests().len(), 1);
+}
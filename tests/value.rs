@@ -0,0 +1,245 @@
+//! Checks that dynamic values use the same encoding as the typed codecs.
+mod common;
+
+use common::scalar;
+
+use krpc_mars::codec::{Bytes, RPCEncodable, Value};
+use krpc_mars::krpc;
+use krpc_mars::krpc::Type_TypeCode as Code;
+use krpc_mars::protobuf::Message;
+
+use std::collections::HashMap;
+
+fn ty(code: Code, types: Vec<krpc::Type>) -> krpc::Type {
+    let mut ty = krpc::Type::new();
+    ty.set_code(code);
+    ty.set_types(types.into());
+    ty
+}
+
+/// Encodes a typed value, decodes it dynamically, then checks that the value encodes back to the
+/// same bytes and converts back to the same typed value.
+fn round_trip<T>(typed: T, ty: &krpc::Type, expected: Value)
+where
+    T: RPCEncodable + krpc_mars::codec::RPCExtractable + PartialEq + std::fmt::Debug,
+{
+    let bytes = typed.encode_to_bytes().unwrap();
+    let value = Value::decode(ty, &bytes).unwrap();
+    assert_eq!(value, expected);
+    assert_eq!(value.encode(ty).unwrap(), bytes);
+    assert_eq!(value.to_typed::<T>(ty).unwrap(), typed);
+    assert_eq!(Value::from_typed(&typed, ty).unwrap(), expected);
+}
+
+#[test]
+fn scalars() {
+    round_trip(1.5f64, &scalar(Code::DOUBLE), Value::Double(1.5));
+    round_trip(-2.5f32, &scalar(Code::FLOAT), Value::Float(-2.5));
+    round_trip(-3i32, &scalar(Code::SINT32), Value::SInt32(-3));
+    round_trip(-4i64, &scalar(Code::SINT64), Value::SInt64(-4));
+    round_trip(5u32, &scalar(Code::UINT32), Value::UInt32(5));
+    round_trip(6u64, &scalar(Code::UINT64), Value::UInt64(6));
+    round_trip(true, &scalar(Code::BOOL), Value::Bool(true));
+    round_trip(
+        String::from("Jeb"),
+        &scalar(Code::STRING),
+        Value::String(String::from("Jeb")),
+    );
+    round_trip(
+        Bytes(vec![0, 255]),
+        &scalar(Code::BYTES),
+        Value::Bytes(vec![0, 255]),
+    );
+}
+
+#[test]
+fn class_ids() {
+    let mut class = scalar(Code::CLASS);
+    class.set_service(String::from("KRPC"));
+    class.set_name(String::from("Expression"));
+    round_trip(
+        krpc_mars::event::Expression::new(42),
+        &class,
+        Value::Class(42),
+    );
+}
+
+#[test]
+fn messages() {
+    let mut status = krpc::Status::new();
+    status.set_version(String::from("0.5.4"));
+    status.set_bytes_read(1024);
+    round_trip(status.clone(), &scalar(Code::STATUS), Value::Status(status));
+
+    let mut stream = krpc::Stream::new();
+    stream.set_id(7);
+    round_trip(stream.clone(), &scalar(Code::STREAM), Value::Stream(stream));
+}
+
+#[test]
+fn nested_collections() {
+    let positions = vec![(1.0f64, vec![String::from("a")]), (2.0, vec![])];
+    let tuple = ty(
+        Code::TUPLE,
+        vec![
+            scalar(Code::DOUBLE),
+            ty(Code::LIST, vec![scalar(Code::STRING)]),
+        ],
+    );
+    round_trip(
+        positions,
+        &ty(Code::LIST, vec![tuple]),
+        Value::List(vec![
+            Value::Tuple(vec![
+                Value::Double(1.0),
+                Value::List(vec![Value::String(String::from("a"))]),
+            ]),
+            Value::Tuple(vec![Value::Double(2.0), Value::List(vec![])]),
+        ]),
+    );
+}
+
+#[test]
+fn dictionaries_and_sets() {
+    let mut dictionary = krpc::Dictionary::new();
+    let mut entry = krpc::DictionaryEntry::new();
+    entry.set_key(String::from("fuel").encode_to_bytes().unwrap());
+    entry.set_value(vec![3u64, 4].encode_to_bytes().unwrap());
+    dictionary.mut_entries().push(entry);
+    let bytes = dictionary.write_to_bytes().unwrap();
+
+    let ty = ty(
+        Code::DICTIONARY,
+        vec![
+            scalar(Code::STRING),
+            ty(Code::SET, vec![scalar(Code::UINT64)]),
+        ],
+    );
+    let value = Value::decode(&ty, &bytes).unwrap();
+    assert_eq!(
+        value,
+        Value::Dictionary(vec![(
+            Value::String(String::from("fuel")),
+            Value::Set(vec![Value::UInt64(3), Value::UInt64(4)]),
+        )])
+    );
+
+    let typed: HashMap<String, std::collections::HashSet<u64>> = value.to_typed(&ty).unwrap();
+    assert_eq!(typed["fuel"], [3, 4].into_iter().collect());
+}
+
+#[test]
+fn type_mismatch() {
+    let list = ty(Code::LIST, vec![scalar(Code::DOUBLE)]);
+    let value = Value::List(vec![Value::Double(1.0), Value::Bool(true)]);
+    match value.encode(&list) {
+        Err(krpc_mars::error::RPCError::TypeMismatch { expected, found }) => {
+            assert_eq!(expected, "DOUBLE");
+            assert_eq!(found, "BOOL");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn missing_type_parameters() {
+    let list = ty(Code::LIST, vec![]);
+    let value = Value::List(vec![Value::Double(1.0)]);
+    match value.encode(&list) {
+        Err(krpc_mars::error::RPCError::Decode {
+            expected, found, ..
+        }) => {
+            assert_eq!(expected, "a type parameter at index 0");
+            assert_eq!(found, "LIST()");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn nulls() {
+    use krpc_mars::codec::RPCExtractable;
+    use krpc_mars::event::Expression;
+
+    fn extract<T: RPCExtractable>(bytes: &[u8]) -> T {
+        let mut input = krpc_mars::protobuf::CodedInputStream::from_bytes(bytes);
+        T::extract_value(&mut input).unwrap()
+    }
+
+    assert!(None::<String>.encode_to_bytes().unwrap().is_empty());
+    assert_eq!(extract::<Option<String>>(&[]), None);
+    let jeb = Some(String::from("Jeb")).encode_to_bytes().unwrap();
+    assert_eq!(extract::<Option<String>>(&jeb), Some(String::from("Jeb")));
+
+    assert_eq!(None::<Expression>.encode_to_bytes().unwrap(), vec![0]);
+    assert_eq!(extract::<Option<Expression>>(&[0]), None);
+    assert_eq!(
+        extract::<Option<Expression>>(&[3]),
+        Some(Expression::new(3))
+    );
+
+    let class = scalar(Code::CLASS);
+    assert_eq!(Value::None.encode(&class).unwrap(), vec![0]);
+    assert!(Value::None
+        .encode(&scalar(Code::STRING))
+        .unwrap()
+        .is_empty());
+    match Value::None.encode(&scalar(Code::DOUBLE)) {
+        Err(krpc_mars::error::RPCError::TypeMismatch { expected, found }) => {
+            assert_eq!(expected, "DOUBLE");
+            assert_eq!(found, "NONE");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn large_tuples_and_dictionaries() {
+    let double = scalar(Code::DOUBLE);
+    round_trip(
+        (1.0f64, 2.0f64, 3.0f64, 4.0f64, 5.0f64, true),
+        &ty(
+            Code::TUPLE,
+            vec![
+                double.clone(),
+                double.clone(),
+                double.clone(),
+                double.clone(),
+                double,
+                scalar(Code::BOOL),
+            ],
+        ),
+        Value::Tuple(vec![
+            Value::Double(1.0),
+            Value::Double(2.0),
+            Value::Double(3.0),
+            Value::Double(4.0),
+            Value::Double(5.0),
+            Value::Bool(true),
+        ]),
+    );
+
+    let twelve = (
+        0u32, 1u32, 2u32, 3u32, 4u32, 5u32, 6u32, 7u32, 8u32, 9u32, 10u32, 11u32,
+    );
+    let ty12 = ty(Code::TUPLE, vec![scalar(Code::UINT32); 12]);
+    round_trip(
+        twelve,
+        &ty12,
+        Value::Tuple((0..12).map(Value::UInt32).collect()),
+    );
+
+    let mut dictionary = HashMap::new();
+    dictionary.insert(String::from("LiquidFuel"), 0.5f32);
+    round_trip(
+        dictionary,
+        &ty(
+            Code::DICTIONARY,
+            vec![scalar(Code::STRING), scalar(Code::FLOAT)],
+        ),
+        Value::Dictionary(vec![(
+            Value::String(String::from("LiquidFuel")),
+            Value::Float(0.5),
+        )]),
+    );
+}
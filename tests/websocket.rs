@@ -0,0 +1,145 @@
+//! Runs the WebSocket clients against a local stand-in for the kRPC WebSocket server.
+#![cfg(feature = "websocket")]
+
+use krpc_mars::client::CallHandle;
+use krpc_mars::codec::Limits;
+use krpc_mars::error::RPCError;
+use krpc_mars::krpc;
+use krpc_mars::protobuf::Message;
+use krpc_mars::stream::StreamHandle;
+use krpc_mars::websocket;
+
+use std::net::TcpListener;
+use std::sync::mpsc;
+
+use tungstenite::handshake::server::{Request, Response};
+
+const CLIENT_ID: &[u8] = &[0xfb, 0xff, 0x01, 0x02];
+
+/// Accepts one WebSocket connection and reports the query string it was opened with.
+#[allow(clippy::result_large_err)] // The callback signature is imposed by tungstenite.
+fn accept(
+    listener: &TcpListener,
+    queries: &mpsc::Sender<String>,
+) -> tungstenite::WebSocket<std::net::TcpStream> {
+    let (sock, _) = listener.accept().unwrap();
+    tungstenite::accept_hdr(sock, |req: &Request, resp: Response| {
+        queries
+            .send(req.uri().query().unwrap_or_default().to_string())
+            .unwrap();
+        Ok(resp)
+    })
+    .unwrap()
+}
+
+fn read_request(ws: &mut tungstenite::WebSocket<std::net::TcpStream>) -> krpc::Request {
+    match ws.read().unwrap() {
+        tungstenite::Message::Binary(bytes) => krpc::Request::parse_from_bytes(&bytes).unwrap(),
+        m => panic!("unexpected message: {:?}", m),
+    }
+}
+
+fn reply(ws: &mut tungstenite::WebSocket<std::net::TcpStream>, value: Vec<u8>) {
+    let mut result = krpc::ProcedureResult::new();
+    result.set_value(value);
+    let mut resp = krpc::Response::new();
+    resp.mut_results().push(result);
+    ws.send(tungstenite::Message::Binary(resp.write_to_bytes().unwrap()))
+        .unwrap();
+}
+
+#[test]
+fn rpc_and_stream_over_websocket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (queries_tx, queries) = mpsc::channel();
+
+    let server = std::thread::spawn(move || {
+        let mut rpc = accept(&listener, &queries_tx);
+
+        let req = read_request(&mut rpc);
+        assert_eq!(req.get_calls()[0].get_procedure(), "GetClientID");
+        let mut id = vec![CLIENT_ID.len() as u8];
+        id.extend_from_slice(CLIENT_ID);
+        reply(&mut rpc, id);
+
+        let req = read_request(&mut rpc);
+        assert_eq!(req.get_calls()[0].get_procedure(), "get_UT");
+        reply(&mut rpc, 42f64.to_le_bytes().to_vec());
+
+        let mut stream = accept(&listener, &queries_tx);
+        let mut update = krpc::StreamUpdate::new();
+        let mut result = krpc::StreamResult::new();
+        result.set_id(7);
+        result.mut_result().set_value(vec![1]);
+        update.mut_results().push(result);
+        stream
+            .send(tungstenite::Message::Binary(
+                update.write_to_bytes().unwrap(),
+            ))
+            .unwrap();
+    });
+
+    let mut client = websocket::connect("Jeb & co", &url).unwrap();
+    assert_eq!(queries.recv().unwrap(), "name=Jeb%20%26%20co");
+
+    let mut proc_call = krpc::ProcedureCall::new();
+    proc_call.set_service(String::from("SpaceCenter"));
+    proc_call.set_procedure(String::from("get_UT"));
+    let ut = client.mk_call(&CallHandle::<f64>::new(proc_call)).unwrap();
+    assert_eq!(ut, 42.0);
+
+    let mut stream_client = websocket::connect_stream(&client, &url).unwrap();
+    assert_eq!(
+        queries.recv().unwrap(),
+        "client_identifier=%2B%2F8BAg%3D%3D"
+    );
+
+    let update = stream_client.recv_update().unwrap();
+    let value = update.get_result(&StreamHandle::<bool>::new(7)).unwrap();
+    assert_eq!(value, Some(true));
+
+    server.join().unwrap();
+}
+
+#[test]
+fn oversized_frame() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (queries_tx, _queries) = mpsc::channel();
+
+    let server = std::thread::spawn(move || {
+        let mut rpc = accept(&listener, &queries_tx);
+
+        read_request(&mut rpc);
+        let mut id = vec![CLIENT_ID.len() as u8];
+        id.extend_from_slice(CLIENT_ID);
+        reply(&mut rpc, id);
+
+        read_request(&mut rpc);
+        let mut name = vec![0x80, 0x08];
+        name.extend([b'x'; 1024]);
+        reply(&mut rpc, name);
+        // Wait for the client to give up on the connection.
+        let _ = rpc.read();
+    });
+
+    let mut client = websocket::connect("test", &url).unwrap();
+    client.set_limits(Limits {
+        max_frame_size: 512,
+        ..Limits::default()
+    });
+    let e = client
+        .mk_call(&krpc_mars::core::get_client_name())
+        .unwrap_err();
+    match e {
+        RPCError::LimitExceeded { what, max, .. } => {
+            assert_eq!(what, "frame size");
+            assert_eq!(max, 512);
+        }
+        e => panic!("not a limit error: {:?}", e),
+    }
+
+    drop(client);
+    server.join().unwrap();
+}